@@ -16,6 +16,7 @@ TrapQuit() {
 
 trap TrapQuit EXIT
 
+export CONDUIT_CONFIG="conduit - test.toml"
 cargo build
 cargo run &
 cargo test
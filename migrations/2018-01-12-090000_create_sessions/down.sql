@@ -0,0 +1,3 @@
+ALTER TABLE public.Users DROP COLUMN TokenVersion;
+
+drop TABLE public.Sessions;
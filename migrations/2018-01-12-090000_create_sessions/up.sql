@@ -0,0 +1,27 @@
+CREATE SEQUENCE public.sessions_id_seq;
+
+CREATE TABLE public.Sessions (
+                Id INTEGER NOT NULL DEFAULT nextval('public.sessions_id_seq'),
+                UserId INTEGER NOT NULL,
+                TokenHash VARCHAR(64) NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                ExpiresAt TIMESTAMP NOT NULL,
+                RevokedAt TIMESTAMP,
+                CONSTRAINT pk_sessions PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.sessions_id_seq OWNED BY public.Sessions.Id;
+
+CREATE UNIQUE INDEX ix_sessions_tokenhash
+ ON public.Sessions
+ ( TokenHash ASC );
+
+ALTER TABLE public.Sessions ADD CONSTRAINT fk_sessions_users
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Users ADD COLUMN TokenVersion INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,25 @@
+CREATE SEQUENCE public.passwordresets_id_seq;
+
+CREATE TABLE public.PasswordResets (
+                Id INTEGER NOT NULL DEFAULT nextval('public.passwordresets_id_seq'),
+                UserId INTEGER NOT NULL,
+                TokenHash VARCHAR(64) NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                ExpiresAt TIMESTAMP NOT NULL,
+                UsedAt TIMESTAMP,
+                CONSTRAINT pk_passwordresets PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.passwordresets_id_seq OWNED BY public.PasswordResets.Id;
+
+CREATE UNIQUE INDEX ix_passwordresets_tokenhash
+ ON public.PasswordResets
+ ( TokenHash ASC );
+
+ALTER TABLE public.PasswordResets ADD CONSTRAINT fk_passwordresets_users
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
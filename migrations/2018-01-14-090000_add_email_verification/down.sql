@@ -0,0 +1,3 @@
+drop TABLE public.EmailVerifications;
+
+ALTER TABLE public.Users DROP COLUMN Verified;
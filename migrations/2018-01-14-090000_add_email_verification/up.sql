@@ -0,0 +1,30 @@
+-- accounts created before verification existed count as verified
+ALTER TABLE public.Users ADD COLUMN Verified BOOLEAN NOT NULL DEFAULT TRUE;
+ALTER TABLE public.Users ALTER COLUMN Verified SET DEFAULT FALSE;
+
+CREATE SEQUENCE public.emailverifications_id_seq;
+
+CREATE TABLE public.EmailVerifications (
+                Id INTEGER NOT NULL DEFAULT nextval('public.emailverifications_id_seq'),
+                UserId INTEGER NOT NULL,
+                TokenHash VARCHAR(64) NOT NULL,
+                Email VARCHAR(50) NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                ExpiresAt TIMESTAMP NOT NULL,
+                UsedAt TIMESTAMP,
+                CONSTRAINT pk_emailverifications PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.emailverifications_id_seq OWNED BY public.EmailVerifications.Id;
+
+CREATE UNIQUE INDEX ix_emailverifications_tokenhash
+ ON public.EmailVerifications
+ ( TokenHash ASC );
+
+ALTER TABLE public.EmailVerifications ADD CONSTRAINT fk_emailverifications_users
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
@@ -0,0 +1,3 @@
+ALTER TABLE public.Users DROP CONSTRAINT ck_users_role;
+
+ALTER TABLE public.Users DROP COLUMN Role;
@@ -0,0 +1,5 @@
+-- promote the first admin by hand: UPDATE public.Users SET Role = 'admin' WHERE UserName = '...';
+ALTER TABLE public.Users ADD COLUMN Role VARCHAR(20) NOT NULL DEFAULT 'user';
+
+ALTER TABLE public.Users ADD CONSTRAINT ck_users_role
+CHECK (Role IN ('user', 'moderator', 'admin'));
@@ -0,0 +1 @@
+ALTER TABLE public.Users ADD COLUMN SuspendedAt TIMESTAMP;
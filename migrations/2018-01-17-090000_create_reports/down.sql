@@ -0,0 +1,4 @@
+drop TABLE public.Reports;
+
+ALTER TABLE public.Comments DROP COLUMN Hidden;
+ALTER TABLE public.Articles DROP COLUMN Hidden;
@@ -0,0 +1,55 @@
+ALTER TABLE public.Articles ADD COLUMN Hidden BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE public.Comments ADD COLUMN Hidden BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE SEQUENCE public.reports_id_seq;
+
+CREATE TABLE public.Reports (
+                Id INTEGER NOT NULL DEFAULT nextval('public.reports_id_seq'),
+                ArticleId INTEGER NOT NULL,
+                CommentId INTEGER,
+                Reporter INTEGER NOT NULL,
+                Reason VARCHAR(500) NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                ResolvedAt TIMESTAMP,
+                Resolution VARCHAR(20),
+                CONSTRAINT pk_reports PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.reports_id_seq OWNED BY public.Reports.Id;
+
+CREATE INDEX ix_reports_resolvedat
+ ON public.Reports
+ ( ResolvedAt ASC );
+
+-- one open report per reader and article or comment
+CREATE UNIQUE INDEX ix_reports_open_article_reporter
+ ON public.Reports
+ ( ArticleId ASC, Reporter ASC )
+ WHERE CommentId IS NULL AND ResolvedAt IS NULL;
+
+CREATE UNIQUE INDEX ix_reports_open_comment_reporter
+ ON public.Reports
+ ( CommentId ASC, Reporter ASC )
+ WHERE CommentId IS NOT NULL AND ResolvedAt IS NULL;
+
+ALTER TABLE public.Reports ADD CONSTRAINT fk_reports_articles
+FOREIGN KEY (ArticleId)
+REFERENCES public.Articles (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Reports ADD CONSTRAINT fk_reports_comments
+FOREIGN KEY (CommentId)
+REFERENCES public.Comments (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Reports ADD CONSTRAINT fk_reports_users
+FOREIGN KEY (Reporter)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
@@ -0,0 +1,32 @@
+CREATE SEQUENCE public.userblocks_id_seq;
+
+CREATE TABLE public.UserBlocks (
+                Id INTEGER NOT NULL DEFAULT nextval('public.userblocks_id_seq'),
+                BlockerId INTEGER NOT NULL,
+                BlockedId INTEGER NOT NULL,
+                Kind VARCHAR(10) NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                CONSTRAINT pk_userblocks PRIMARY KEY (Id),
+                CONSTRAINT ck_userblocks_kind CHECK (Kind IN ('block', 'mute'))
+);
+
+
+ALTER SEQUENCE public.userblocks_id_seq OWNED BY public.UserBlocks.Id;
+
+CREATE UNIQUE INDEX ix_userblocks_blocker_blocked_kind
+ ON public.UserBlocks
+ ( BlockerId ASC, BlockedId ASC, Kind ASC );
+
+ALTER TABLE public.UserBlocks ADD CONSTRAINT fk_userblocks_blocker
+FOREIGN KEY (BlockerId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.UserBlocks ADD CONSTRAINT fk_userblocks_blocked
+FOREIGN KEY (BlockedId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
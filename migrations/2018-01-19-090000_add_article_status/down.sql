@@ -0,0 +1,5 @@
+ALTER TABLE public.Articles DROP CONSTRAINT ck_articles_status;
+
+ALTER TABLE public.Articles DROP COLUMN TakenDownAt;
+ALTER TABLE public.Articles DROP COLUMN PublishAt;
+ALTER TABLE public.Articles DROP COLUMN Status;
@@ -0,0 +1,7 @@
+ALTER TABLE public.Articles ADD COLUMN Status VARCHAR(10) NOT NULL DEFAULT 'published';
+ALTER TABLE public.Articles ADD COLUMN PublishAt TIMESTAMP;
+-- set while a moderator holds the article back, only a moderator can publish it again
+ALTER TABLE public.Articles ADD COLUMN TakenDownAt TIMESTAMP;
+
+ALTER TABLE public.Articles ADD CONSTRAINT ck_articles_status
+CHECK (Status IN ('draft', 'published'));
@@ -0,0 +1,34 @@
+CREATE SEQUENCE public.articlerevisions_id_seq;
+
+CREATE TABLE public.ArticleRevisions (
+                Id INTEGER NOT NULL DEFAULT nextval('public.articlerevisions_id_seq'),
+                ArticleId INTEGER NOT NULL,
+                Number INTEGER NOT NULL,
+                Title VARCHAR(250) NOT NULL,
+                Description VARCHAR(250) NOT NULL,
+                Body text NOT NULL,
+                EditorId INTEGER NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                CONSTRAINT pk_articlerevisions PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.articlerevisions_id_seq OWNED BY public.ArticleRevisions.Id;
+
+CREATE UNIQUE INDEX ix_articlerevisions_article_number
+ ON public.ArticleRevisions
+ ( ArticleId ASC, Number ASC );
+
+ALTER TABLE public.ArticleRevisions ADD CONSTRAINT fk_articlerevisions_article
+FOREIGN KEY (ArticleId)
+REFERENCES public.Articles (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.ArticleRevisions ADD CONSTRAINT fk_articlerevisions_editor
+FOREIGN KEY (EditorId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
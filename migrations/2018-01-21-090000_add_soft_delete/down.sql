@@ -0,0 +1,2 @@
+ALTER TABLE public.Comments DROP COLUMN DeletedAt;
+ALTER TABLE public.Articles DROP COLUMN DeletedAt;
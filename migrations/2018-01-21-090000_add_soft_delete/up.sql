@@ -0,0 +1,2 @@
+ALTER TABLE public.Articles ADD COLUMN DeletedAt TIMESTAMP;
+ALTER TABLE public.Comments ADD COLUMN DeletedAt TIMESTAMP;
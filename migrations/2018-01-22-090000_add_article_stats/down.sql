@@ -0,0 +1,3 @@
+ALTER TABLE public.Articles DROP COLUMN Excerpt;
+ALTER TABLE public.Articles DROP COLUMN ReadingTimeMinutes;
+ALTER TABLE public.Articles DROP COLUMN WordCount;
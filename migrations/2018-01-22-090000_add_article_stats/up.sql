@@ -0,0 +1,11 @@
+ALTER TABLE public.Articles ADD COLUMN WordCount INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE public.Articles ADD COLUMN ReadingTimeMinutes INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE public.Articles ADD COLUMN Excerpt VARCHAR(250) NOT NULL DEFAULT '';
+
+-- a close estimate for existing articles, the next update computes them exactly
+UPDATE public.Articles SET
+    WordCount = CASE WHEN trim(Body) = '' THEN 0
+                     ELSE array_length(regexp_split_to_array(trim(Body), '\s+'), 1) END,
+    Excerpt = CASE WHEN Description = '' THEN left(Body, 200) ELSE Description END;
+
+UPDATE public.Articles SET ReadingTimeMinutes = (WordCount + 199) / 200;
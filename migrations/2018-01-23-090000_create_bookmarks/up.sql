@@ -0,0 +1,30 @@
+CREATE SEQUENCE public.bookmarks_id_seq;
+
+CREATE TABLE public.Bookmarks (
+                Id INTEGER NOT NULL DEFAULT nextval('public.bookmarks_id_seq'),
+                UserId INTEGER NOT NULL,
+                ArticleId INTEGER NOT NULL,
+                CreatedAt TIMESTAMP NOT NULL,
+                CONSTRAINT pk_bookmarks PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.bookmarks_id_seq OWNED BY public.Bookmarks.Id;
+
+CREATE UNIQUE INDEX ix_bookmarks_user_article
+ ON public.Bookmarks
+ ( UserId ASC, ArticleId ASC );
+
+ALTER TABLE public.Bookmarks ADD CONSTRAINT fk_bookmarks_user
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Bookmarks ADD CONSTRAINT fk_bookmarks_article
+FOREIGN KEY (ArticleId)
+REFERENCES public.Articles (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
@@ -0,0 +1 @@
+DROP INDEX public.ix_favoritedarticles_article_user;
@@ -0,0 +1,16 @@
+-- keep the oldest of each duplicated row, then make sure no new duplicates get in
+DELETE FROM public.FavoritedArticles a
+ USING public.FavoritedArticles b
+ WHERE a.ArticleId = b.ArticleId AND a.UserId = b.UserId AND a.Id > b.Id;
+
+DELETE FROM public.Followings a
+ USING public.Followings b
+ WHERE a.FollowingId = b.FollowingId AND a.FollowerId = b.FollowerId AND a.Id > b.Id;
+
+CREATE UNIQUE INDEX ix_favoritedarticles_article_user
+ ON public.FavoritedArticles
+ ( ArticleId ASC, UserId ASC );
+
+CREATE UNIQUE INDEX IF NOT EXISTS ix_followings
+ ON public.Followings
+ ( FollowingId ASC, FollowerId ASC );
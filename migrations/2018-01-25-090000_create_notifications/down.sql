@@ -0,0 +1,2 @@
+drop TABLE public.NotificationPreferences;
+drop TABLE public.Notifications;
@@ -0,0 +1,74 @@
+CREATE SEQUENCE public.notifications_id_seq;
+
+CREATE TABLE public.Notifications (
+                Id INTEGER NOT NULL DEFAULT nextval('public.notifications_id_seq'),
+                UserId INTEGER NOT NULL,
+                ActorId INTEGER NOT NULL,
+                Kind VARCHAR(10) NOT NULL,
+                ArticleId INTEGER,
+                CommentId INTEGER,
+                CreatedAt TIMESTAMP NOT NULL,
+                ReadAt TIMESTAMP,
+                CONSTRAINT pk_notifications PRIMARY KEY (Id),
+                CONSTRAINT ck_notifications_kind CHECK (Kind IN ('follow', 'favorite', 'comment'))
+);
+
+
+ALTER SEQUENCE public.notifications_id_seq OWNED BY public.Notifications.Id;
+
+CREATE INDEX ix_notifications_user
+ ON public.Notifications
+ ( UserId ASC, CreatedAt DESC );
+
+ALTER TABLE public.Notifications ADD CONSTRAINT fk_notifications_user
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Notifications ADD CONSTRAINT fk_notifications_actor
+FOREIGN KEY (ActorId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Notifications ADD CONSTRAINT fk_notifications_article
+FOREIGN KEY (ArticleId)
+REFERENCES public.Articles (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+ALTER TABLE public.Notifications ADD CONSTRAINT fk_notifications_comment
+FOREIGN KEY (CommentId)
+REFERENCES public.Comments (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+CREATE SEQUENCE public.notificationpreferences_id_seq;
+
+CREATE TABLE public.NotificationPreferences (
+                Id INTEGER NOT NULL DEFAULT nextval('public.notificationpreferences_id_seq'),
+                UserId INTEGER NOT NULL,
+                Kind VARCHAR(10) NOT NULL,
+                Enabled BOOLEAN NOT NULL,
+                CONSTRAINT pk_notificationpreferences PRIMARY KEY (Id),
+                CONSTRAINT ck_notificationpreferences_kind CHECK (Kind IN ('follow', 'favorite', 'comment'))
+);
+
+
+ALTER SEQUENCE public.notificationpreferences_id_seq OWNED BY public.NotificationPreferences.Id;
+
+CREATE UNIQUE INDEX ix_notificationpreferences_user_kind
+ ON public.NotificationPreferences
+ ( UserId ASC, Kind ASC );
+
+ALTER TABLE public.NotificationPreferences ADD CONSTRAINT fk_notificationpreferences_user
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
@@ -0,0 +1,2 @@
+drop TABLE public.WebhookDeliveries;
+drop TABLE public.Webhooks;
@@ -0,0 +1,62 @@
+CREATE SEQUENCE public.webhooks_id_seq;
+
+CREATE TABLE public.Webhooks (
+                Id INTEGER NOT NULL DEFAULT nextval('public.webhooks_id_seq'),
+                UserId INTEGER NOT NULL,
+                Url VARCHAR(500) NOT NULL,
+                Secret VARCHAR(64) NOT NULL,
+                Events VARCHAR(200) NOT NULL,
+                IsGlobal BOOLEAN NOT NULL DEFAULT FALSE,
+                CreatedAt TIMESTAMP NOT NULL,
+                CONSTRAINT pk_webhooks PRIMARY KEY (Id)
+);
+
+
+ALTER SEQUENCE public.webhooks_id_seq OWNED BY public.Webhooks.Id;
+
+CREATE INDEX ix_webhooks_user
+ ON public.Webhooks
+ ( UserId ASC );
+
+ALTER TABLE public.Webhooks ADD CONSTRAINT fk_webhooks_user
+FOREIGN KEY (UserId)
+REFERENCES public.Users (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
+
+CREATE SEQUENCE public.webhookdeliveries_id_seq;
+
+CREATE TABLE public.WebhookDeliveries (
+                Id INTEGER NOT NULL DEFAULT nextval('public.webhookdeliveries_id_seq'),
+                WebhookId INTEGER NOT NULL,
+                Event VARCHAR(20) NOT NULL,
+                Payload TEXT NOT NULL,
+                Status VARCHAR(10) NOT NULL DEFAULT 'pending',
+                Attempts INTEGER NOT NULL DEFAULT 0,
+                ResponseStatus INTEGER,
+                LastError TEXT,
+                CreatedAt TIMESTAMP NOT NULL,
+                NextAttemptAt TIMESTAMP,
+                DeliveredAt TIMESTAMP,
+                CONSTRAINT pk_webhookdeliveries PRIMARY KEY (Id),
+                CONSTRAINT ck_webhookdeliveries_status CHECK (Status IN ('pending', 'delivered', 'failed'))
+);
+
+
+ALTER SEQUENCE public.webhookdeliveries_id_seq OWNED BY public.WebhookDeliveries.Id;
+
+CREATE INDEX ix_webhookdeliveries_webhook
+ ON public.WebhookDeliveries
+ ( WebhookId ASC, CreatedAt DESC );
+
+CREATE INDEX ix_webhookdeliveries_status_next_attempt
+ ON public.WebhookDeliveries
+ ( Status ASC, NextAttemptAt ASC );
+
+ALTER TABLE public.WebhookDeliveries ADD CONSTRAINT fk_webhookdeliveries_webhook
+FOREIGN KEY (WebhookId)
+REFERENCES public.Webhooks (Id)
+ON DELETE RESTRICT
+ON UPDATE RESTRICT
+NOT DEFERRABLE;
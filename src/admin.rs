@@ -0,0 +1,535 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// What admins get to see of an account, everything but the password hash.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct AdminUser {
+    pub id: i32,
+    pub email: String,
+    pub username: String,
+    pub role: String,
+    pub verified: bool,
+    pub suspendedAt: Option<NaiveDateTime>,
+}
+
+#[cfg(feature = "diesel")]
+fn admin_user(user: User) -> AdminUser {
+    AdminUser {
+        id: user.id,
+        email: user.email,
+        username: user.username,
+        role: user.role,
+        verified: user.verified,
+        suspendedAt: user.suspendedAt,
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct AdminUserResult {
+    user: AdminUser,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct UsersResult {
+    users: Vec<AdminUser>,
+}
+
+impl Container<AdminUser> for UsersResult {
+    fn create_new_with_items(users: Vec<AdminUser>) -> UsersResult {
+        UsersResult { users: users }
+    }
+}
+
+fn users_result(_: UsersResult) {}
+
+#[derive(Debug)]
+pub struct UserFilter {
+    pub search: String,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+#[cfg(feature = "diesel")]
+pub fn is_suspended(user_id: i32) -> bool {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    users
+        .filter(id.eq(user_id))
+        .select(suspendedat)
+        .first::<Option<NaiveDateTime>>(&connection)
+        .map(|suspended| suspended.is_some())
+        .unwrap_or(false)
+}
+
+#[cfg(not(feature = "diesel"))]
+pub fn is_suspended(_: i32) -> bool {
+    false
+}
+
+/// The checks every admin endpoint starts with; hands back `res` and the user named in the url.
+#[cfg(feature = "diesel")]
+fn require_admin_target(res: Response, logged_id: i32, user_name: &str) -> Option<(Response, User)> {
+    use schema::users::dsl::*;
+
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return None,
+    };
+    let res = match require_permission(res, logged_id, Permission::ManageUsers, None) {
+        Some(res) => res,
+        None => return None,
+    };
+
+    let connection = establish_connection();
+    match users.filter(username.eq(user_name)).first(&connection) {
+        Ok(user) => Some((res, user)),
+        Err(_) => {
+            send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found"));
+            None
+        }
+    }
+}
+
+/// Admins can't suspend, delete or demote themselves, it could leave nobody to undo it.
+#[cfg(feature = "diesel")]
+fn require_other_user(res: Response, logged_id: i32, user: &User) -> Option<Response> {
+    if user.id == logged_id {
+        let errors = ValidationErrors::with("username", "can't be your own account");
+        send_errors(res, StatusCode::UnprocessableEntity, errors);
+        return None;
+    }
+    Some(res)
+}
+
+#[cfg(feature = "diesel")]
+fn get_users_by_filter(filter: UserFilter) -> Vec<AdminUser> {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    let pattern = format!(
+        "%{}%",
+        filter.search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_")
+    );
+
+    users
+        .filter(username.like(&pattern).or(email.like(&pattern)))
+        .order(id.asc())
+        .offset(filter.offset)
+        .limit(filter.limit)
+        .load::<User>(&connection)
+        .expect("Error loading users")
+        .into_iter()
+        .map(admin_user)
+        .collect()
+}
+
+pub fn list_users_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let res = match require_permission(res, logged_id, Permission::ManageUsers, None) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let url_params = caps[0].replace("/api/admin/users", "");
+    let url_params = url_params.trim_left_matches('?');
+
+    let (offset, limit) = parse_paging(url_params);
+    let mut filter = UserFilter {
+        search: String::new(),
+        offset: offset,
+        limit: limit,
+    };
+    for param in url_params.split('&') {
+        let name_value: Vec<&str> = param.split('=').collect();
+        if name_value.len() < 2 {
+            continue;
+        }
+
+        if name_value[0] == "search" {
+            filter.search = name_value[1].to_string();
+        };
+    }
+
+    #[cfg(feature = "diesel")]
+    process_container(res, users_result, get_users_by_filter, filter);
+}
+
+#[cfg(feature = "diesel")]
+fn set_suspended(params: (i32, bool)) -> Option<AdminUserResult> {
+    use schema::users::dsl::*;
+
+    let (user_id, suspend) = params;
+    let suspended_at = if suspend { Some(Utc::now().naive_utc()) } else { None };
+
+    let connection = establish_connection();
+    let user: User = diesel::update(users.filter(id.eq(user_id)))
+        .set(suspendedat.eq(suspended_at))
+        .get_result(&connection)
+        .expect("Error suspending user");
+    Some(AdminUserResult { user: admin_user(user) })
+}
+
+fn suspension_handler(req: Request, res: Response, c: Captures, suspend: bool) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let user_name = &caps[0].replace("/api/admin/users/", "").replace("/suspend", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, user) = match require_admin_target(res, logged_id, user_name) {
+            Some(found) => found,
+            None => return,
+        };
+        let res = match require_other_user(res, logged_id, &user) {
+            Some(res) => res,
+            None => return,
+        };
+
+        process(res, set_suspended, (user.id, suspend))
+    }
+}
+
+pub fn suspend_user_handler(req: Request, res: Response, c: Captures) {
+    suspension_handler(req, res, c, true)
+}
+
+pub fn unsuspend_user_handler(req: Request, res: Response, c: Captures) {
+    suspension_handler(req, res, c, false)
+}
+
+/// Locks the user out until they pick a new password through the mailed reset link.
+#[cfg(feature = "diesel")]
+fn force_password_reset(user: User) -> Option<bool> {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(users.filter(id.eq(user.id)))
+        .set(token.eq(hash_password(&new_random_token())))
+        .execute(&connection)
+        .expect("Error clearing password");
+
+    revoke_all_sessions(user.id);
+    create_password_reset(&connection, user).expect("Error saving password reset");
+    None
+}
+
+pub fn force_password_reset_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let user_name = &caps[0].replace("/api/admin/users/", "").replace("/password-reset", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, user) = match require_admin_target(res, logged_id, user_name) {
+            Some(found) => found,
+            None => return,
+        };
+
+        process(res, force_password_reset, user)
+    }
+}
+
+/// Removes the user with everything they wrote or were part of, all or nothing.
+#[cfg(feature = "diesel")]
+fn delete_user(user_id: i32) -> Option<bool> {
+    use diesel::expression::dsl::any;
+    use schema::{articlerevisions, articles, articletags, bookmarks, comments, emailverifications,
+                 favoritedarticles, followings, notificationpreferences, notifications, passwordresets, reports,
+                 sessions, userblocks, users, webhookdeliveries, webhooks};
+
+    let connection = establish_connection();
+    connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let article_ids: Vec<i32> = articles::table
+                .filter(articles::author.eq(user_id))
+                .select(articles::id)
+                .load(&connection)?;
+
+            let comment_ids: Vec<i32> = comments::table
+                .filter(comments::author.eq(user_id))
+                .select(comments::id)
+                .load(&connection)?;
+
+            for comment_id in comment_ids {
+                diesel::delete(reports::table.filter(reports::commentid.eq(comment_id)))
+                    .execute(&connection)?;
+            }
+            diesel::delete(reports::table.filter(
+                reports::reporter.eq(user_id).or(reports::articleid.eq(any(&article_ids))),
+            )).execute(&connection)?;
+            // notifications about comments on the user's articles point at those articles too
+            for article_id in &article_ids {
+                diesel::delete(notifications::table.filter(notifications::articleid.eq(*article_id)))
+                    .execute(&connection)?;
+            }
+            diesel::delete(notifications::table.filter(
+                notifications::userid.eq(user_id).or(notifications::actorid.eq(user_id)),
+            )).execute(&connection)?;
+            diesel::delete(notificationpreferences::table.filter(notificationpreferences::userid.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(favoritedarticles::table.filter(
+                favoritedarticles::userid.eq(user_id).or(favoritedarticles::articleid.eq(any(&article_ids))),
+            )).execute(&connection)?;
+            diesel::delete(bookmarks::table.filter(
+                bookmarks::userid.eq(user_id).or(bookmarks::articleid.eq(any(&article_ids))),
+            )).execute(&connection)?;
+            diesel::delete(comments::table.filter(
+                comments::author.eq(user_id).or(comments::articleid.eq(any(&article_ids))),
+            )).execute(&connection)?;
+            diesel::delete(articletags::table.filter(articletags::articleid.eq(any(&article_ids))))
+                .execute(&connection)?;
+            diesel::delete(articlerevisions::table.filter(
+                articlerevisions::editorid.eq(user_id).or(articlerevisions::articleid.eq(any(&article_ids))),
+            )).execute(&connection)?;
+            diesel::delete(articles::table.filter(articles::author.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(followings::table.filter(
+                followings::followerid.eq(user_id).or(followings::followingid.eq(user_id)),
+            )).execute(&connection)?;
+            diesel::delete(userblocks::table.filter(
+                userblocks::blockerid.eq(user_id).or(userblocks::blockedid.eq(user_id)),
+            )).execute(&connection)?;
+            let webhook_ids: Vec<i32> = webhooks::table
+                .filter(webhooks::userid.eq(user_id))
+                .select(webhooks::id)
+                .load(&connection)?;
+            diesel::delete(webhookdeliveries::table.filter(webhookdeliveries::webhookid.eq(any(&webhook_ids))))
+                .execute(&connection)?;
+            diesel::delete(webhooks::table.filter(webhooks::userid.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(sessions::table.filter(sessions::userid.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(passwordresets::table.filter(passwordresets::userid.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(emailverifications::table.filter(emailverifications::userid.eq(user_id)))
+                .execute(&connection)?;
+            diesel::delete(users::table.filter(users::id.eq(user_id)))
+                .execute(&connection)?;
+            Ok(())
+        })
+        .expect("Error deleting user");
+    None
+}
+
+pub fn delete_user_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let user_name = &caps[0].replace("/api/admin/users/", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, user) = match require_admin_target(res, logged_id, user_name) {
+            Some(found) => found,
+            None => return,
+        };
+        let res = match require_other_user(res, logged_id, &user) {
+            Some(res) => res,
+            None => return,
+        };
+
+        process(res, delete_user, user.id)
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn change_role(params: (i32, Role)) -> Option<AdminUserResult> {
+    use schema::users::dsl::*;
+
+    let (user_id, new_role) = params;
+    let connection = establish_connection();
+
+    let user: User = diesel::update(users.filter(id.eq(user_id)))
+        .set(role.eq(new_role.name()))
+        .get_result(&connection)
+        .expect("Error changing role");
+    Some(AdminUserResult { user: admin_user(user) })
+}
+
+pub fn change_role_handler(req: Request, res: Response, c: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let user_name = &caps[0].replace("/api/admin/users/", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, user) = match require_admin_target(res, logged_id, user_name) {
+            Some(found) => found,
+            None => return,
+        };
+        let res = match require_other_user(res, logged_id, &user) {
+            Some(res) => res,
+            None => return,
+        };
+
+        let change: ChangeRole = match serde_json::from_str(&body) {
+            Ok(change) => change,
+            Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+        };
+
+        let errors = change.user.validate();
+        if !errors.is_empty() {
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+
+        process(res, change_role, (user.id, Role::parse(&change.user.role).unwrap()))
+    }
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+fn login_admin() -> String {
+    let (admin_name, admin_email) = register_jacob();
+    set_role(&admin_name, Role::Admin);
+    login_jacob(admin_email, user::JACOB_PASSWORD.to_string())
+}
+
+#[cfg(test)]
+#[test]
+fn change_role_test() {
+    let client = Client::new();
+    let (user_name, user_email) = register_jacob();
+    let user_jwt = login_jacob(user_email, user::JACOB_PASSWORD.to_string());
+
+    let url = format!("http://localhost:6767/api/admin/users/{}", user_name);
+    let body = r#"{"user":{"role": "moderator"}}"#;
+
+    // only admins may change roles
+    let res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: user_jwt.to_owned() }))
+        .body(body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let admin_jwt = login_admin();
+
+    let mut res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: admin_jwt.to_owned() }))
+        .body(body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result: AdminUserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.user.role, "moderator");
+
+    let res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: admin_jwt }))
+        .body(r#"{"user":{"role": "root"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+#[test]
+fn suspend_user_test() {
+    let client = Client::new();
+    let admin_jwt = login_admin();
+    let (user_name, user_email) = register_jacob();
+    let user_jwt = login_jacob(user_email.to_owned(), user::JACOB_PASSWORD.to_string());
+    let (_, refresh_token) = login_with_refresh_token(&user_email);
+
+    let url = format!("http://localhost:6767/api/admin/users/{}/suspend", user_name);
+    let res = client
+        .post(&url)
+        .header(Authorization(Bearer { token: admin_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client
+        .get("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: user_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    // no new tokens for a suspended account either
+    let res = client
+        .post("http://localhost:6767/api/users/refresh")
+        .body(&format!(r#"{{"refreshToken": "{}"}}"#, refresh_token))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let res = client
+        .delete(&url)
+        .header(Authorization(Bearer { token: admin_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client
+        .get("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: user_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn list_and_delete_user_test() {
+    let client = Client::new();
+    let admin_jwt = login_admin();
+    let (_, slug, user_name) = login_create_article(false);
+
+    let url = format!("http://localhost:6767/api/admin/users?search={}", user_name);
+    let mut res = client
+        .get(&url)
+        .header(Authorization(Bearer { token: admin_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result: UsersResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.users.len(), 1);
+    assert_eq!(result.users[0].username, user_name);
+    assert!(!buffer.contains("\"token\""));
+
+    let res = client
+        .delete(&format!("http://localhost:6767/api/admin/users/{}", user_name))
+        .header(Authorization(Bearer { token: admin_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut res = client
+        .get(&url)
+        .header(Authorization(Bearer { token: admin_jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: UsersResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.users.len(), 0);
+
+    // the article went with its author
+    assert!(find_article(&slug).is_none());
+}
@@ -37,7 +37,7 @@ use super::*;
 #[derive(Debug)]
 #[allow(non_snake_case)]
 pub struct ArticlesResult {
-    articles: Vec<Article>,
+    pub articles: Vec<Article>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -60,6 +60,23 @@ impl Container<Article> for ArticlesResult {
     }
 }
 
+/// Values of the Status column, an empty status on create means published.
+pub static ARTICLE_STATUSES: &'static [&'static str] = &["draft", "published"];
+
+/// Published articles scheduled for later only go out once `publishAt` has passed.
+pub fn is_published(article: &Article, now: NaiveDateTime) -> bool {
+    article.status == "published" && article.publishAt.map_or(true, |publish_at| publish_at <= now)
+}
+
+/// Hidden, draft and scheduled articles stay visible to their author and to moderators.
+#[cfg(feature = "diesel")]
+pub fn can_view(article: &Article, viewer_id: i32) -> bool {
+    if !article.hidden && is_published(article, Utc::now().naive_utc()) {
+        return true;
+    }
+    article.author == viewer_id || (viewer_id > 0 && get_role(viewer_id) >= Role::Moderator)
+}
+
 pub fn get_tag_names<'a>(_a: &str) -> Option<TagsResult> {
         use models::Tag;
         use schema::tags;
@@ -129,6 +146,9 @@ fn get_simple_article_from_row(row: tiberius::query::QueryRow) -> Option<Article
         bio: bio.map(|s| s.to_string()),
         image: image.map(|s| s.to_string()),
         following: following,
+        followersCount: None,
+        followingCount: None,
+        articlesCount: None,
     };
 
     let result = Article {
@@ -192,7 +212,12 @@ pub fn create_article<'a>(mut article: AdvancedArticle) -> Option<ArticleResult>
         body: &cloned_article.body,
         createdat: cloned_article.createdAt,
         updatedat: cloned_article.updatedAt,
-        author: article.author
+        author: article.author,
+        status: &cloned_article.status,
+        publishat: cloned_article.publishAt,
+        wordcount: cloned_article.wordCount,
+        readingtimeminutes: cloned_article.readingTimeMinutes,
+        excerpt: &cloned_article.excerpt,
     };
 
     let article_result: Article = diesel::insert(&new_article)
@@ -204,19 +229,45 @@ pub fn create_article<'a>(mut article: AdvancedArticle) -> Option<ArticleResult>
     
     let result = article.clone();
     create_article_tag(article);
+    publish_new_article(&article_result, &result);
+    dispatch_webhook_event("article.created", vec![result.author], &result);
     
     Some(ArticleResult { article: result,} )
 }
 
 pub fn create_article_handler(req: Request, res: Response, _: Captures) {
     let (body, logged_in_user_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_in_user_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let res = match require_verified(res, logged_in_user_id, Restricted::Articles) {
+        Some(res) => res,
+        None => return,
+    };
 
-    let container: IncomingArticleResult = serde_json::from_str(&body).unwrap();
+    let container: CreateArticle = match serde_json::from_str(&body) {
+        Ok(container) => container,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("article", "can't be read")),
+    };
     let incoming_article = container.article;
+
+    let errors = incoming_article.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+
     let title: String = incoming_article.title;
     let description: String = incoming_article.description;
     let article_body: String = incoming_article.body;
     let tag_list: Vec<String> = incoming_article.tagList.unwrap_or(Vec::new());
+    let status: String = if incoming_article.status.is_empty() {
+        "published".to_string()
+    } else {
+        incoming_article.status
+    };
+    let publish_at: Option<NaiveDateTime> = incoming_article.publishAt;
+    let stats = article_stats(&description, &article_body);
     let slug: String = slugify(&title);
     //let tags: &str = &tag_list.join(",");
 
@@ -225,6 +276,11 @@ pub fn create_article_handler(req: Request, res: Response, _: Captures) {
         use chrono::prelude::*;
         let utc: DateTime<Utc> = Utc::now();
 
+        // deleted articles keep their slug until they are purged, they may still be restored
+        if slug_taken(&slug, 0) {
+            return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("title", "has already been taken"));
+        }
+
         let article = AdvancedArticle {
             id : -1,
             title: title,
@@ -237,6 +293,12 @@ pub fn create_article_handler(req: Request, res: Response, _: Captures) {
             tagList: tag_list,
             favorited: false,
             favoritesCount: 0,
+            status: status,
+            publishAt: publish_at,
+            bodyHtml: None,
+            wordCount: stats.word_count,
+            readingTimeMinutes: stats.reading_time_minutes,
+            excerpt: stats.excerpt,
         };
         process(res, create_article, article );
     }
@@ -288,25 +350,26 @@ fn unfavorite_article<'a>(article_id: i32, user_id: i32) -> Option<bool> {
     use schema::favoritedarticles::dsl::*;
     let connection = establish_connection();
 
-    let relationship: ArticleUser = favoritedarticles
-        .filter(articleid.eq(article_id).and(userid.eq(user_id)))
-        .first(&connection)
-        .unwrap();
-
-    diesel::delete(favoritedarticles.filter(id.eq(relationship.id))).execute(&connection).expect("Failed to unfavorite article");
+    // unfavoriting what isn't favorited leaves nothing to do
+    diesel::delete(favoritedarticles.filter(articleid.eq(article_id).and(userid.eq(user_id))))
+        .execute(&connection)
+        .expect("Failed to unfavorite article");
     None
 }
 
 #[cfg(feature = "diesel")]
-fn favorite_article<'a>(new_relationship: NewArticleUser) {  
+fn favorite_article<'a>(new_relationship: NewArticleUser) -> bool {  
     let connection = establish_connection();
 
+    use diesel::pg::upsert::*;
     use schema::favoritedarticles;
 
-    let _relationship: ArticleUser = diesel::insert(&new_relationship)
+    // favoriting twice is the same as favoriting once, true only the first time
+    let inserted = diesel::insert(&new_relationship.on_conflict_do_nothing())
     .into(favoritedarticles::table)
-    .get_result(&connection)
+    .execute(&connection)
     .expect("Error saving new favorited article relationship");    
+    inserted > 0
 }
 
 #[cfg(feature = "diesel")]
@@ -327,17 +390,29 @@ pub fn favorite_article_handler(req: Request, res: Response, c: Captures) {
     #[cfg(feature = "diesel")]
     {
         let (_, logged_in_user_id) = prepare_parameters(req);
+        let res = match require_login(res, logged_in_user_id) {
+            Some(res) => res,
+            None => return,
+        };
         let caps = c.unwrap();
         let url_slug = &caps[0].replace("/api/articles/", "").replace(
             "/favorite","",
         );
 
-        let article = get_advanced_article(url_slug);
+        let article = match find_article(url_slug) {
+            Some(ref article) if can_view(article, logged_in_user_id) => article.clone(),
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
         let new_relationship = NewArticleUser {
             userid : logged_in_user_id,
-            articleid : article.unwrap().article.id,
+            articleid : article.id,
     }   ;
-        favorite_article(new_relationship);
+        if favorite_article(new_relationship) {
+            notify(article.author, logged_in_user_id, NotificationKind::Favorite, Some(article.id), None);
+            if let Some(result) = get_advanced_article(url_slug) {
+                publish_event(Topic::Article(article.id), logged_in_user_id, "favorite", &result.article);
+            }
+        }
         process(res, get_advanced_article, url_slug );
     };
 
@@ -359,14 +434,21 @@ pub fn unfavorite_article_handler(req: Request, res: Response, c: Captures) {
     #[cfg(feature = "diesel")]
     {
         let (_, logged_in_user_id) = prepare_parameters(req);
+        let res = match require_login(res, logged_in_user_id) {
+            Some(res) => res,
+            None => return,
+        };
         let caps = c.unwrap();
         let url_slug = &caps[0].replace("/api/articles/", "").replace(
             "/favorite","",
         );
 
-        let article: ArticleResult = get_advanced_article(url_slug).unwrap();
+        let article = match find_article(url_slug) {
+            Some(article) => article,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
 
-        unfavorite_article(article.article.id, logged_in_user_id);
+        unfavorite_article(article.id, logged_in_user_id);
         process(res, get_advanced_article, url_slug);
     };
 
@@ -385,8 +467,15 @@ pub fn unfavorite_article_handler(req: Request, res: Response, c: Captures) {
 
 fn articles_result(_: ArticlesResult) {}
 
+fn rendered_articles_result(_: RenderedArticlesResult) {}
+
 pub fn feed_handler(req: Request, res: Response, c: Captures) {
+    let render = wants_html(&req);
     let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     let caps = c.unwrap();
     let url_params = &caps[0].replace("/api/articles/feed?", "");
@@ -411,12 +500,19 @@ pub fn feed_handler(req: Request, res: Response, c: Captures) {
     #[cfg(feature = "diesel")] {
         let filter: FilterParams = FilterParams {
             tag: "",
-            author: &logged_id.to_string(),
+            author: "",
             favorited: "",
             offset: offset,
             limit: limit,
+            viewer: logged_id,
+            drafts: false,
+            following: true,
         };
-        process_container(res, articles_result, get_articles_feed_by_filter, filter);
+        if render {
+            process_container(res, rendered_articles_result, get_rendered_articles_by_filter, filter);
+        } else {
+            process_container(res, articles_result, get_articles_by_filter, filter);
+        }
     }
 
     #[cfg(feature = "tiberius")]
@@ -445,172 +541,114 @@ pub struct FilterParams<'a> {
     pub favorited: &'a str,
     pub offset: i32,
     pub limit: i32,
+    pub viewer: i32,
+    pub drafts: bool,
+    /// Only articles by authors the viewer follows.
+    pub following: bool,
 }
 
-fn get_articles_feed_by_filter(params: FilterParams) -> Vec<Article> {
-    use schema::followings;
-    use schema::users;
+/// The viewer's own articles that aren't out yet, drafts and scheduled ones alike.
+fn get_unpublished_articles(author_id: i32, offset: i32, limit: i32) -> Vec<Article> {
+    use schema::articles::dsl::*;
 
-    let connection = establish_connection();    
-
-    let followed_users_ids : Vec<i32> =
-        followings::table
-        .filter(followings::followerid.eq(params.author.parse::<i32>().unwrap()))
-        .select(followings::followingid)
-        // .offset(params.offset as i64)
-        // .limit(params.limit as i64)
-        .load(&connection).expect("Error loading articles");
-
-    let users_iter : Vec<User> =
-        followed_users_ids
-        .into_iter()
-        .map(|user_id| 
-            users::table
-            .filter(users::id.eq(user_id))
-            .first(&connection)
-            .unwrap()
-        ).collect();
-
-    let result : Vec<Article> =
-        users_iter
-        .into_iter()
-        .flat_map(|u : User| 
-            Article::belonging_to(&u)
-            //.order(articles::id.desc())
-            .load::<Article>(&connection)
-            .expect("Error loading articles with author")
-        ).collect();
-        // .into_iter()
-        // .map(|v| v.clone())
-        // .collect::<Vec<Article>>();
-    result
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+
+    articles
+        .filter(author.eq(author_id))
+        .filter(deletedat.is_null())
+        .filter(status.ne("published").or(publishat.gt(Some(now))))
+        .order(id.desc())
+        .offset(offset as i64)
+        .limit(limit as i64)
+        .load::<Article>(&connection)
+        .expect("Error loading drafts")
 }
 
-fn get_articles_by_filter(params: FilterParams) -> Vec<Article> {
-    use diesel::prelude::*;
+/// Articles out for everyone, narrowed down by tag, author, favoriting user and followed
+/// authors, latest publication first. Everything the viewer doesn't get to see is filtered
+/// in the query, so offset and limit count only what is left.
+pub fn get_articles_by_filter(params: FilterParams) -> Vec<Article> {
+    use diesel::expression::dsl::{all, any};
+    use diesel::expression::sql_literal::sql;
+    use diesel::types::Timestamp;
     use schema::users;
     use schema::articles;
     use schema::tags;
     use schema::articletags;
     use schema::favoritedarticles;
-    use std::collections::HashSet;
+    use schema::followings;
+
+    if params.drafts {
+        return get_unpublished_articles(params.viewer, params.offset, params.limit);
+    }
 
-    let connection = establish_connection();    
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
 
-    let mut with_tag: HashSet<i32> = HashSet::new();
-    let mut with_author: HashSet<i32> = HashSet::new();
-    let mut with_favorited_by: HashSet<i32> = HashSet::new();
-    let mut intersection: &HashSet<i32> = &HashSet::new();
-    let mut for_intersection: Vec<&HashSet<_>> = Vec::new();
+    let mut query = articles::table
+        .into_boxed()
+        .filter(articles::deletedat.is_null())
+        .filter(articles::hidden.eq(false))
+        .filter(articles::status.eq("published"))
+        .filter(articles::publishat.is_null().or(articles::publishat.le(Some(now))))
+        .filter(articles::author.ne(all(get_hidden_authors(params.viewer))));
 
     if params.tag != "" {
-        with_tag = articletags::table
+        let with_tag: Vec<i32> = articletags::table
             .inner_join(tags::table)
-            //.filter(articletags::tagid.eq(tags::id).and(tags::tag.eq(params.tag)))
             .filter(tags::tag.eq(params.tag))
             .select(articletags::articleid)
-            .load::<i32>(&connection)
-            .expect("Error loading articles with tag")
-            .into_iter().collect();
-        if with_tag.len() > 0{
-            for_intersection.push(&with_tag);
-        }
-        
+            .load(&connection)
+            .expect("Error loading articles with tag");
+        query = query.filter(articles::id.eq(any(with_tag)));
     }
 
     if params.author != "" {
-        let current_author = users::table
+        let current_author: User = match users::table
                     .filter(users::username.eq(params.author))
-                    .first::<User>(&connection)
-                    .expect("Error loading author for articles");
-
-        with_author = Article::belonging_to(&current_author)
-                    .load::<Article>(&connection)
-                    .expect("Error loading articles with author")
-                    .into_iter()
-                    .map(|a| a.id)
-                    .collect();
-
-        if with_author.len() > 0{
-            for_intersection.push(&with_author);
-        }
+                    .first(&connection) {
+            Ok(user) => user,
+            Err(_) => return Vec::new(),
+        };
+        query = query.filter(articles::author.eq(current_author.id));
     }
+
     if params.favorited != "" {
-        with_favorited_by = favoritedarticles::table
+        let with_favorited_by: Vec<i32> = favoritedarticles::table
             .inner_join(users::table)
             .filter(users::username.eq(params.favorited))
             .select(favoritedarticles::articleid)
-            .load::<i32>(&connection)
-            .expect("Error loading articles with favorited by")
-            .into_iter().collect();
-        if with_favorited_by.len() > 0{
-            for_intersection.push(&with_favorited_by);
-        }
-    }
-
-    if for_intersection.len() > 1 {
-        let last = for_intersection.pop().unwrap();
-        intersection =  last;
-
-        for f in for_intersection {
-            let intersection = &intersection.intersection(f).into_iter().map(|v| v.clone()).collect::<HashSet<i32>>();
-        }               
-    }
-    else {
-        intersection =  for_intersection.pop().unwrap();
+            .load(&connection)
+            .expect("Error loading articles with favorited by");
+        query = query.filter(articles::id.eq(any(with_favorited_by)));
     }
 
-    println!("intersection size 1: {}", intersection.len().to_string());
-    let mut int_vec: Vec<&i32> = intersection.into_iter().collect();
-
-    //sort
-    int_vec.sort();
-    println!("intersection size 2: {}", int_vec.len().to_string());
-
-    //apply limit
-    let final_length = if params.limit > intersection.len() as i32 { intersection.len() as i32 } else { params.limit };
-    int_vec.truncate(final_length as usize);
-    println!("intersection size 3: {}", int_vec.len().to_string());
-    //apply offset
-    if params.offset < final_length {
-        int_vec = int_vec.split_off(params.offset as usize);
-        println!("intersection size 4: {}", int_vec.len().to_string());
+    if params.following {
+        let followed_users_ids: Vec<i32> = followings::table
+            .filter(followings::followerid.eq(params.viewer))
+            .select(followings::followingid)
+            .load(&connection)
+            .expect("Error loading followings");
+        query = query.filter(articles::author.eq(any(followed_users_ids)));
     }
 
-    let fav_vec: Vec<i32> = with_favorited_by.clone().into_iter().collect();
-    let tag_vec: Vec<i32> = with_tag.clone().into_iter().collect();
-    let auth_vec: Vec<i32> = with_author.clone().into_iter().collect();
-    
-    println!("intersection size 5: {}", int_vec.len().to_string());
-    for x in &fav_vec {
-        println!("favorited: {}", x.to_string());
-    }
-    for x in &tag_vec {
-        println!("tags: {}", x.to_string());
-    }
-    for x in &auth_vec {
-        println!("author's articles: {}", x.to_string());
-    }
-    for x in &int_vec {
-        println!("intersection: {}", x.to_string());
-    }
+    // scheduled articles take their place in the list when they come out
+    query
+        .order(sql::<Timestamp>("COALESCE(publishat, createdat) DESC, id DESC"))
+        .offset(params.offset as i64)
+        .limit(params.limit as i64)
+        .load::<Article>(&connection)
+        .expect("Error loading articles list")
+}
 
-    let result : Vec<Article> = intersection
-                                .into_iter()
-                                .map(|item_id| 
-                                        articles::table
-                                        .filter(articles::id.eq(item_id))
-                                        .first::<Article>(&connection)
-                                        .expect("Error loading articles list")
-                                )
-                                .into_iter()
-                                .collect();
-
-    result
+fn get_rendered_articles_by_filter(params: FilterParams) -> Vec<serde_json::Value> {
+    render_articles(get_articles_by_filter(params))
 }
 
 pub fn list_article_handler(req: Request, res: Response, c: Captures) {
-    let (_, _) = prepare_parameters(req);
+    let render = wants_html(&req);
+    let (_, logged_id) = prepare_parameters(req);
 
     let caps = c.unwrap();
     let url_params = &caps[0].replace("/api/articles?", "");
@@ -624,6 +662,7 @@ pub fn list_article_handler(req: Request, res: Response, c: Captures) {
     let mut tag = "";
     let mut author = "";
     let mut favorited = "";
+    let mut drafts = false;
 
     for param in &parsed_params {
         let name_value: Vec<&str> = param.split('=').collect();
@@ -634,6 +673,8 @@ pub fn list_article_handler(req: Request, res: Response, c: Captures) {
             author = name_value[1];
         } else if name_value[0] == "favorited" {
             favorited = name_value[1];
+        } else if name_value[0] == "drafts" {
+            drafts = name_value.get(1) == Some(&"true");
         } else if name_value[0] == "offset" {
             offset = name_value[1].parse::<i32>().unwrap();
         } else if name_value[0] == "limit" {
@@ -641,6 +682,16 @@ pub fn list_article_handler(req: Request, res: Response, c: Captures) {
         };
     }
 
+    // drafts are private, listing them needs to know whose
+    let res = if drafts {
+        match require_login(res, logged_id) {
+            Some(res) => res,
+            None => return,
+        }
+    } else {
+        res
+    };
+
     #[cfg(feature = "diesel")]
     let filter: FilterParams = FilterParams {
         tag: tag,
@@ -648,10 +699,19 @@ pub fn list_article_handler(req: Request, res: Response, c: Captures) {
         favorited: favorited,
         offset: offset,
         limit: limit,
+        viewer: logged_id,
+        drafts: drafts,
+        following: false,
     };
 
     #[cfg(feature = "diesel")]
-    process_container(res, articles_result, get_articles_by_filter, filter);
+    {
+        if render {
+            process_container(res, rendered_articles_result, get_rendered_articles_by_filter, filter);
+        } else {
+            process_container(res, articles_result, get_articles_by_filter, filter);
+        }
+    }
 
     #[cfg(feature = "tiberius")]
     process_container(
@@ -683,20 +743,10 @@ order by Articles.Id DESC OFFSET @p2 ROWS FETCH NEXT @p3 ROWS Only"#,
     );
 }
 
-fn delete_tags_for_article(art: Article) {
+pub fn delete_tags_for_article(conn: &PgConnection, article_id: i32) -> QueryResult<usize> {
     use schema::articletags::dsl::*;
-    let conn = establish_connection();
-    
-    let tag_links : Vec<ArticleTag> =
-        ArticleTag::belonging_to(&art)
-        .load(&conn)
-        .expect("Error loading article tag links");
-    
-    for link in tag_links {
-        diesel::delete(articletags.filter(id.eq(link.id)))
-        .execute(&conn)
-        .expect("Error deleting article tag link");
-    }
+
+    diesel::delete(articletags.filter(articleid.eq(article_id))).execute(conn)
 }
 
 fn get_tags_for_article(article: &Article, conn: PgConnection) -> Vec<String> {
@@ -714,16 +764,32 @@ fn get_tags_for_article(article: &Article, conn: PgConnection) -> Vec<String> {
     tag_objs.into_iter().map(|t| t.tag).collect()
 }
 
-pub fn get_article(url_slug: &str) -> Article {
+pub fn find_article(url_slug: &str) -> Option<Article> {
     use schema::articles::dsl::*;
     let connection = establish_connection();
 
-    let result: Article = articles
+    articles
         .filter(slug.eq(url_slug))
+        .filter(deletedat.is_null())
         .first(&connection)
-        .unwrap();
+        .ok()
+}
 
-    result
+/// Whether an article other than `except_id` has `url_slug`, deleted ones included since the index is.
+pub fn slug_taken(url_slug: &str, except_id: i32) -> bool {
+    use schema::articles::dsl::*;
+    let connection = establish_connection();
+
+    articles
+        .filter(slug.eq(url_slug))
+        .filter(id.ne(except_id))
+        .select(id)
+        .first::<i32>(&connection)
+        .is_ok()
+}
+
+pub fn get_article(url_slug: &str) -> Article {
+    find_article(url_slug).unwrap()
 }
 
 pub fn get_advanced_article(url_slug: &str) -> Option<ArticleResult> {
@@ -746,17 +812,44 @@ pub fn get_advanced_article(url_slug: &str) -> Option<ArticleResult> {
         author : article.author,
         favoritesCount: favorites_count,
         favorited: favorites_count > 0,
+        status : article.status,
+        publishAt : article.publishAt,
+        bodyHtml : None,
+        wordCount : article.wordCount,
+        readingTimeMinutes : article.readingTimeMinutes,
+        excerpt : article.excerpt,
     };
 
     Some(ArticleResult { article: result,})
 }
 
+#[cfg(feature = "diesel")]
+fn get_rendered_article(url_slug: &str) -> Option<ArticleResult> {
+    get_advanced_article(url_slug).map(|mut result| {
+        result.article.bodyHtml = Some(article_body_html(result.article.id, &result.article.body));
+        result
+    })
+}
+
 pub fn get_article_handler(req: Request, res: Response, c: Captures) {
-    let (_, _) = prepare_parameters(req);
+    let render = wants_html(&req);
+    let (_, logged_id) = prepare_parameters(req);
     let caps = c.unwrap();
-    let url_slug = &caps[0].replace("/api/articles/", "");
+    let url_path = caps[0].replace("/api/articles/", "");
+    let url_slug = url_path.split('?').next().unwrap_or("");
 
-    #[cfg(feature = "diesel")] process(res, get_advanced_article, (url_slug));
+    #[cfg(feature = "diesel")] {
+        let visible = find_article(url_slug).map_or(false, |article| can_view(&article, logged_id));
+        if !visible {
+            return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found"));
+        }
+
+        if render {
+            process(res, get_rendered_article, url_slug);
+        } else {
+            process(res, get_advanced_article, (url_slug));
+        }
+    }
 
     #[cfg(feature = "tiberius")]
     process_and_return_article(
@@ -769,67 +862,88 @@ pub fn get_article_handler(req: Request, res: Response, c: Captures) {
     );
 }
 
+/// Applies the changes on top of the article as it is under the row lock, after keeping
+/// that as a revision, so concurrent edits each build on the one before.
 #[cfg(feature = "diesel")]
-pub fn update_article<'a>(new_article: UpdatedArticle) -> Option<ArticleResult> {
-    let conn = establish_connection();
-
-    let result = new_article.save_changes::<Article>(&conn).unwrap();
+fn update_article(params: (i32, UpdateArticleDetail, i32)) -> Option<ArticleResult> {
+    use models::UpdatedArticle;
 
-    get_advanced_article(&result.slug)
-}
-
-pub fn update_article_handler(req: Request, res: Response, c: Captures) {
-    let (request_body, _) = prepare_parameters(req);
-
-    let caps = c.unwrap();
-    let url_slug = &caps[0].replace("/api/articles/", "");
-    println!("slug {}", &url_slug);
-
-    #[cfg(feature = "diesel")] {
-        use models::UpdatedArticle;
-
-        let incoming_article: UpdateArticle = serde_json::from_str(&request_body).unwrap();
-        
-        let article_result : ArticleResult = get_advanced_article(url_slug).unwrap();
-        let original = article_result.article;
-        let old_id = original.id;
-        let old_author = original.author;
-        let old_created = original.createdAt;
-        let old_updated = original.updatedAt;
+    let (article_id, changes, editor_id) = params;
+    let conn = establish_connection();
 
+    let result = conn.transaction::<_, diesel::result::Error, _>(|| {
+        let original = lock_article(&conn, article_id)?;
+        save_revision(&conn, &original, editor_id)?;
 
-        let new_title: &str = incoming_article
-            .article
-            .title
-            .as_ref()
-            .map(|x| &**x)
-            .unwrap_or(&original.title);
-        let new_body: &str = incoming_article
-            .article
-            .body
-            .as_ref()
-            .map(|x| &**x)
-            .unwrap_or(&original.body);
-        let new_description: &str = incoming_article
-            .article
+        let new_title: &str = changes.title.as_ref().map(|x| &**x).unwrap_or(&original.title);
+        let new_body: &str = changes.body.as_ref().map(|x| &**x).unwrap_or(&original.body);
+        let new_description: &str = changes
             .description
             .as_ref()
             .map(|x| &**x)
             .unwrap_or(&original.description);
         let new_slug: &str = &slugify(new_title);
+        let stats = article_stats(new_description, new_body);
 
         let new_article = UpdatedArticle {
-            id : old_id,
+            id : original.id,
             slug : new_slug,
             title : new_title,
             description : new_description,
             body : new_body,
-            author : old_author,
-            createdat : old_created,
-            updatedat : old_updated,
+            author : original.author,
+            createdat : original.createdAt,
+            updatedat : original.updatedAt,
+            wordcount : stats.word_count,
+            readingtimeminutes : stats.reading_time_minutes,
+            excerpt : &stats.excerpt,
         };
+        new_article.save_changes::<Article>(&conn)
+    }).expect("Error updating article");
 
-        process(res, update_article, new_article )
+    let updated = get_advanced_article(&result.slug);
+    if let Some(ref updated) = updated {
+        dispatch_webhook_event("article.updated", vec![updated.article.author], &updated.article);
+    }
+    updated
+}
+
+pub fn update_article_handler(req: Request, res: Response, c: Captures) {
+    let (request_body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let url_slug = &caps[0].replace("/api/articles/", "");
+    println!("slug {}", &url_slug);
+
+    #[cfg(feature = "diesel")] {
+        let incoming_article: UpdateArticle = match serde_json::from_str(&request_body) {
+            Ok(incoming_article) => incoming_article,
+            Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("article", "can't be read")),
+        };
+
+        let errors = incoming_article.article.validate();
+        if !errors.is_empty() {
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+
+        let current = match find_article(url_slug) {
+            Some(article) => article,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::EditArticle, Some(current.author)) {
+            Some(res) => res,
+            None => return,
+        };
+        if let Some(ref new_title) = incoming_article.article.title {
+            if slug_taken(&slugify(new_title.to_owned()), current.id) {
+                return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("title", "has already been taken"));
+            }
+        }
+        process(res, update_article, (current.id, incoming_article.article, logged_id))
     }
 
     #[cfg(feature = "tiberius")]
@@ -860,19 +974,18 @@ pub fn update_article_handler(req: Request, res: Response, c: Captures) {
 
 #[cfg(feature = "diesel")]
 fn delete_article (url_slug: String) -> Option<bool> {
-    use schema::articles::dsl::*;
-    let connection = establish_connection();
-
     let ar = get_article(&url_slug);
-    delete_tags_for_article(ar);
-
-    diesel::delete(articles.filter(slug.eq(url_slug)))
-        .execute(&connection).expect("Failed to delete an article");
+    soft_delete_article(ar.id);
+    dispatch_webhook_event("article.deleted", vec![ar.author], &ar);
     None
 }
 
 pub fn delete_article_handler(req: Request, res: Response, c: Captures) {
     let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     let caps = c.unwrap();
     let slug = &caps[0].replace("/api/articles/", "");
@@ -880,6 +993,15 @@ pub fn delete_article_handler(req: Request, res: Response, c: Captures) {
 
     #[cfg(feature = "diesel")] 
     {
+        let owner_id = match find_article(slug) {
+            Some(article) => article.author,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::DeleteArticle, Some(owner_id)) {
+            Some(res) => res,
+            None => return,
+        };
+
         process(res, delete_article, slug.to_owned());
     };
 
@@ -897,6 +1019,96 @@ pub fn delete_article_handler(req: Request, res: Response, c: Captures) {
     );
 }
 
+#[cfg(feature = "diesel")]
+fn set_article_status(params: (i32, &'static str, Option<NaiveDateTime>, Option<NaiveDateTime>)) -> Option<bool> {
+    use schema::articles::dsl::*;
+
+    let (article_id, new_status, publish_at, taken_down_at) = params;
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+
+    let before: Article = articles
+        .filter(id.eq(article_id))
+        .first(&connection)
+        .expect("Error loading article");
+    let after: Article = diesel::update(articles.filter(id.eq(article_id)))
+        .set((status.eq(new_status), publishat.eq(publish_at), takendownat.eq(taken_down_at)))
+        .get_result(&connection)
+        .expect("Error changing article status");
+
+    // followers hear of it when it comes out, not again for every publish of a visible one
+    if !is_published(&before, now) {
+        if let Some(result) = get_advanced_article(&after.slug) {
+            publish_new_article(&after, &result.article);
+        }
+    }
+    None
+}
+
+/// Publishes right away, or at `publishAt` when the body carries one.
+pub fn publish_article_handler(req: Request, res: Response, c: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/publish", "");
+    let publish: PublishArticle = serde_json::from_str(&body).unwrap_or_default();
+
+    #[cfg(feature = "diesel")]
+    {
+        let article = match find_article(slug) {
+            Some(article) => article,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::EditArticle, Some(article.author)) {
+            Some(res) => res,
+            None => return,
+        };
+        // a moderator's takedown holds until a moderator lifts it
+        if article.takenDownAt.is_some() && get_role(logged_id) < Role::Moderator {
+            return send_errors(res, StatusCode::Forbidden, ValidationErrors::with("article", "was taken down by a moderator"));
+        }
+
+        process(res, set_article_status, (article.id, "published", publish.article.publishAt, None));
+    }
+}
+
+/// Takes an article back to draft; moderators may do this to anyone's article, which
+/// keeps its author from publishing it again.
+pub fn unpublish_article_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/unpublish", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let article = match find_article(slug) {
+            Some(article) => article,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::UnpublishArticle, Some(article.author)) {
+            Some(res) => res,
+            None => return,
+        };
+
+        let taken_down_at = if article.author == logged_id {
+            article.takenDownAt
+        } else {
+            Some(Utc::now().naive_utc())
+        };
+
+        process(res, set_article_status, (article.id, "draft", None, taken_down_at));
+    }
+}
+
 #[cfg(test)]
 use rand::Rng;
 
@@ -1022,6 +1234,43 @@ fn unfavorite_article_test() {
     assert_eq!(res.status, hyper::Ok);
 }
 
+#[cfg(test)]
+#[test]
+fn repeat_favorite_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}/favorite", slug);
+
+    for _ in 0..2 {
+        let mut res = client
+            .post(&url)
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::Ok);
+
+        let result: ArticleResult = serde_json::from_str(&buffer).unwrap();
+        assert_eq!(result.article.favoritesCount, 1);
+    }
+
+    for _ in 0..2 {
+        let mut res = client
+            .delete(&url)
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::Ok);
+
+        let result: ArticleResult = serde_json::from_str(&buffer).unwrap();
+        assert_eq!(result.article.favoritesCount, 0);
+    }
+}
+
 #[cfg(test)]
 #[test]
 fn get_article_test() {
@@ -1139,6 +1388,8 @@ fn update_article_test() {
     assert_eq!(article.title, title2);
     //assert_eq!(article.description, "CHANGED1");
     assert_eq!(article.body, "CHANGED2");
+    assert_eq!(article.wordCount, 1);
+    assert_eq!(article.readingTimeMinutes, 1);
     //assert_eq!(article.favorited, false);
     //assert_eq!(article.favoritesCount, 0);
     //assert_eq!(article.author.username, user_name);
@@ -1160,3 +1411,154 @@ fn delete_article_test() {
         .unwrap();
     assert_eq!(res.status, hyper::Ok);
 }
+
+#[cfg(test)]
+pub fn create_unpublished_article(jwt: &str, extra: &str) -> String {
+    let client = Client::new();
+    let title = format!("Dragons not yet trained {}-{}", since_the_epoch(), rand::thread_rng().gen_range(0, 1000));
+
+    let body = format!(
+        r#"{{"article": {{"title": "{}","description": "Soon","body": "Patience",{}}}}}"#,
+        title,
+        extra
+    );
+    let res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    slugify(title)
+}
+
+#[cfg(test)]
+#[test]
+fn draft_article_test() {
+    let client = Client::new();
+    let (jwt, _, _) = login_create_article(false);
+
+    let slug = create_unpublished_article(&jwt, r#""status": "draft""#);
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+
+    // nor can others favorite it or read its comments
+    let (_, other_email) = register_jacob();
+    let other_jwt = login_jacob(other_email, user::JACOB_PASSWORD.to_string());
+    let res = client
+        .post(&format!("{}/favorite", url))
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+    let res = client.get(&format!("{}/comments", url)).send().unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+
+    let mut res = client
+        .get("http://localhost:6767/api/articles?drafts=true")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let drafts: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert!(drafts.articles.iter().any(|article| article.slug == slug));
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/publish", slug))
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn draft_paging_test() {
+    let client = Client::new();
+    let (jwt, slug, user_name) = login_create_article(false);
+    create_unpublished_article(&jwt, r#""status": "draft""#);
+
+    // the newer draft doesn't take the only place on the page
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles?author={}&limit=1", user_name))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let articles: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(articles.articles.len(), 1);
+    assert_eq!(articles.articles[0].slug, slug);
+}
+
+#[cfg(test)]
+#[test]
+fn scheduled_article_test() {
+    let client = Client::new();
+    let (jwt, _, _) = login_create_article(false);
+
+    let publish_at = Utc::now().naive_utc() + chrono::Duration::seconds(2);
+    let extra = format!(r#""publishAt": "{}""#, publish_at.format("%Y-%m-%dT%H:%M:%S"));
+    let slug = create_unpublished_article(&jwt, &extra);
+
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+
+    ::std::thread::sleep(::std::time::Duration::from_secs(3));
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn taken_down_article_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+
+    let (moderator_name, moderator_email) = register_jacob();
+    set_role(&moderator_name, Role::Moderator);
+    let moderator_jwt = login_jacob(moderator_email, user::JACOB_PASSWORD.to_string());
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/unpublish", slug))
+        .header(Authorization(Bearer { token: moderator_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // the author can't just put it back
+    let publish_url = format!("http://localhost:6767/api/articles/{}/publish", slug);
+    let res = client
+        .post(&publish_url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let res = client
+        .post(&publish_url)
+        .header(Authorization(Bearer { token: moderator_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // once a moderator has lifted it the author is free again
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/unpublish", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let res = client
+        .post(&publish_url)
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
@@ -0,0 +1,93 @@
+use pulldown_cmark::{Event, Parser, Tag};
+
+/// Average adult silent reading speed, words per minute.
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Longest generated excerpt in characters, the ellipsis not counted.
+const EXCERPT_LENGTH: usize = 200;
+
+/// Figures stored along with an article so lists don't work them out on every request.
+#[derive(Debug, PartialEq)]
+pub struct ArticleStats {
+    pub word_count: i32,
+    pub reading_time_minutes: i32,
+    pub excerpt: String,
+}
+
+/// The words a reader sees in a Markdown body, without markup, links or raw HTML.
+pub fn plain_text(markdown: &str) -> String {
+    let mut text = String::new();
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Text(words) => text.push_str(&words),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            // words on both sides of a block boundary mustn't run together
+            Event::End(Tag::Paragraph) |
+            Event::End(Tag::Header(_)) |
+            Event::End(Tag::BlockQuote) |
+            Event::End(Tag::CodeBlock(_)) |
+            Event::End(Tag::Item) |
+            Event::End(Tag::TableCell) => text.push(' '),
+            _ => {}
+        }
+    }
+    text
+}
+
+/// The first words of `text` up to `max_chars`, with an ellipsis when something was cut.
+pub fn make_excerpt(text: &str, max_chars: usize) -> String {
+    let mut excerpt = String::new();
+    for word in text.split_whitespace() {
+        let length = excerpt.chars().count();
+        let separator = if excerpt.is_empty() { 0 } else { 1 };
+
+        if length + separator + word.chars().count() > max_chars {
+            if excerpt.is_empty() {
+                // a single word too long for the excerpt is cut where it has to be
+                excerpt = word.chars().take(max_chars).collect();
+            }
+            excerpt.push('…');
+            return excerpt;
+        }
+        if separator > 0 {
+            excerpt.push(' ');
+        }
+        excerpt.push_str(word);
+    }
+    excerpt
+}
+
+/// The description is the excerpt when the author wrote one, otherwise it comes from the body.
+pub fn article_stats(description: &str, body: &str) -> ArticleStats {
+    let text = plain_text(body);
+    let word_count = text.split_whitespace().count();
+    let excerpt = if description.trim().is_empty() {
+        make_excerpt(&text, EXCERPT_LENGTH)
+    } else {
+        description.to_string()
+    };
+
+    ArticleStats {
+        word_count: word_count as i32,
+        reading_time_minutes: ((word_count + WORDS_PER_MINUTE - 1) / WORDS_PER_MINUTE) as i32,
+        excerpt: excerpt,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn article_stats_test() {
+    let stats = article_stats("", "# Dragons\n\nYou have to *believe*, [really](http://dragons.example).");
+    assert_eq!(stats.word_count, 6);
+    assert_eq!(stats.reading_time_minutes, 1);
+    assert_eq!(stats.excerpt, "Dragons You have to believe, really.");
+
+    let long_body = vec!["word"; 401].join(" ");
+    let stats = article_stats("Ever wonder how?", &long_body);
+    assert_eq!(stats.reading_time_minutes, 3);
+    assert_eq!(stats.excerpt, "Ever wonder how?");
+
+    assert_eq!(article_stats("", "").reading_time_minutes, 0);
+    assert_eq!(make_excerpt("one two three", 8), "one two…");
+    assert_eq!(make_excerpt("dragonsdragons", 7), "dragons…");
+}
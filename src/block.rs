@@ -0,0 +1,220 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// A block cuts all ties, a mute only keeps the other user's content out of sight.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockKind {
+    Block,
+    Mute,
+}
+
+impl BlockKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            BlockKind::Block => "block",
+            BlockKind::Mute => "mute",
+        }
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn add_block(params: (i32, User, BlockKind)) -> Option<ProfileResult> {
+    use schema::{followings, userblocks};
+
+    let (blocker_id, blocked, kind) = params;
+    let connection = establish_connection();
+
+    let existing: i64 = userblocks::table
+        .filter(userblocks::blockerid.eq(blocker_id))
+        .filter(userblocks::blockedid.eq(blocked.id))
+        .filter(userblocks::kind.eq(kind.name()))
+        .count()
+        .get_result(&connection)
+        .expect("Error loading blocks");
+
+    if existing == 0 {
+        let new_block = NewUserBlock {
+            blockerid: blocker_id,
+            blockedid: blocked.id,
+            kind: kind.name(),
+            createdat: Utc::now().naive_utc(),
+        };
+        diesel::insert(&new_block)
+            .into(userblocks::table)
+            .execute(&connection)
+            .expect("Error saving block");
+    }
+
+    if kind == BlockKind::Block {
+        diesel::delete(followings::table.filter(
+            (followings::followerid.eq(blocker_id).and(followings::followingid.eq(blocked.id)))
+                .or(followings::followerid.eq(blocked.id).and(followings::followingid.eq(blocker_id))),
+        )).execute(&connection)
+            .expect("Error removing follows of blocked user");
+    }
+
+    get_profile_result((blocked, blocker_id))
+}
+
+#[cfg(feature = "diesel")]
+fn remove_block(params: (i32, User, BlockKind)) -> Option<ProfileResult> {
+    use schema::userblocks::dsl::*;
+
+    let (blocker_id, blocked, block_kind) = params;
+    let connection = establish_connection();
+
+    diesel::delete(
+        userblocks
+            .filter(blockerid.eq(blocker_id))
+            .filter(blockedid.eq(blocked.id))
+            .filter(kind.eq(block_kind.name())),
+    ).execute(&connection)
+        .expect("Error removing block");
+
+    get_profile_result((blocked, blocker_id))
+}
+
+/// True when `blocker_id` has blocked (not just muted) `blocked_id`.
+#[cfg(feature = "diesel")]
+pub fn is_blocked(blocker_id: i32, blocked_id: i32) -> bool {
+    use schema::userblocks::dsl::*;
+
+    let connection = establish_connection();
+    let blocks: i64 = userblocks
+        .filter(blockerid.eq(blocker_id))
+        .filter(blockedid.eq(blocked_id))
+        .filter(kind.eq(BlockKind::Block.name()))
+        .count()
+        .get_result(&connection)
+        .expect("Error loading blocks");
+    blocks > 0
+}
+
+/// Authors whose articles and comments `viewer_id` doesn't want to see, blocked or muted.
+#[cfg(feature = "diesel")]
+pub fn get_hidden_authors(viewer_id: i32) -> Vec<i32> {
+    use schema::userblocks::dsl::*;
+
+    if viewer_id <= 0 {
+        return Vec::new();
+    }
+
+    let connection = establish_connection();
+    userblocks
+        .filter(blockerid.eq(viewer_id))
+        .select(blockedid)
+        .load(&connection)
+        .expect("Error loading blocks")
+}
+
+fn block_route_handler(req: Request, res: Response, c: Captures, kind: BlockKind, add: bool) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let profile = &caps[0]
+        .replace("/api/profiles/", "")
+        .replace(&format!("/{}", kind.name()), "");
+
+    #[cfg(feature = "diesel")]
+    {
+        use schema::users::dsl::*;
+
+        let connection = establish_connection();
+        let user: User = match users.filter(username.eq(profile)).first(&connection) {
+            Ok(user) => user,
+            Err(_) => {
+                return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found"));
+            }
+        };
+        if user.id == logged_id {
+            let errors = ValidationErrors::with("username", "can't be your own account");
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+
+        if add {
+            process(res, add_block, (logged_id, user, kind))
+        } else {
+            process(res, remove_block, (logged_id, user, kind))
+        }
+    }
+}
+
+pub fn block_handler(req: Request, res: Response, c: Captures) {
+    block_route_handler(req, res, c, BlockKind::Block, true)
+}
+
+pub fn unblock_handler(req: Request, res: Response, c: Captures) {
+    block_route_handler(req, res, c, BlockKind::Block, false)
+}
+
+pub fn mute_handler(req: Request, res: Response, c: Captures) {
+    block_route_handler(req, res, c, BlockKind::Mute, true)
+}
+
+pub fn unmute_handler(req: Request, res: Response, c: Captures) {
+    block_route_handler(req, res, c, BlockKind::Mute, false)
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn block_test() {
+    let client = Client::new();
+    let (jwt, slug, user_name) = login_create_article(false);
+    let (blocked_jwt, _, blocked_name) = login_create_article(false);
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/profiles/{}/follow", user_name))
+        .header(Authorization(Bearer { token: blocked_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/profiles/{}/block", blocked_name))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // the follow is gone and can't be made again
+    let res = client
+        .post(&format!("http://localhost:6767/api/profiles/{}/follow", user_name))
+        .header(Authorization(Bearer { token: blocked_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments", slug))
+        .header(Authorization(Bearer { token: blocked_jwt }))
+        .body(r#"{"comment": {"body": "His name was my name too."}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let url = format!("http://localhost:6767/api/articles?author={}", blocked_name);
+    let mut res = client
+        .get(&url)
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let articles: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(articles.articles.len(), 0);
+
+    let mut res = client.get(&url).send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let articles: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(articles.articles.len(), 1);
+}
@@ -0,0 +1,220 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// Whether the logged user has an article bookmarked. Bookmarks are private, they are
+/// only ever shown to the user who made them.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct BookmarkState {
+    pub article: String,
+    pub bookmarked: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct BookmarkResult {
+    pub bookmark: BookmarkState,
+}
+
+#[cfg(feature = "diesel")]
+fn add_bookmark(params: (i32, Article)) -> Option<BookmarkResult> {
+    use diesel::pg::upsert::*;
+    use schema::bookmarks::dsl::*;
+
+    let (user_id, article) = params;
+    let connection = establish_connection();
+
+    // bookmarking again keeps the first bookmark and its place in the list
+    let new_bookmark = NewBookmark {
+        userid: user_id,
+        articleid: article.id,
+        createdat: Utc::now().naive_utc(),
+    };
+    diesel::insert(&new_bookmark.on_conflict_do_nothing())
+        .into(bookmarks)
+        .execute(&connection)
+        .expect("Error saving bookmark");
+
+    Some(BookmarkResult {
+        bookmark: BookmarkState {
+            article: article.slug,
+            bookmarked: true,
+        },
+    })
+}
+
+#[cfg(feature = "diesel")]
+fn remove_bookmark(params: (i32, Article)) -> Option<BookmarkResult> {
+    use schema::bookmarks::dsl::*;
+
+    let (user_id, article) = params;
+    let connection = establish_connection();
+
+    diesel::delete(bookmarks.filter(userid.eq(user_id)).filter(articleid.eq(article.id)))
+        .execute(&connection)
+        .expect("Error removing bookmark");
+
+    Some(BookmarkResult {
+        bookmark: BookmarkState {
+            article: article.slug,
+            bookmarked: false,
+        },
+    })
+}
+
+/// Bookmarks go along when their article is purged.
+#[cfg(feature = "diesel")]
+pub fn delete_bookmarks_for(connection: &PgConnection, article_id: i32) -> QueryResult<usize> {
+    use schema::bookmarks::dsl::*;
+
+    diesel::delete(bookmarks.filter(articleid.eq(article_id))).execute(connection)
+}
+
+fn bookmark_route_handler(req: Request, res: Response, c: Captures, add: bool) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/bookmark", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let article = match find_article(slug) {
+            Some(ref article) if can_view(article, logged_id) => article.clone(),
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+
+        if add {
+            process(res, add_bookmark, (logged_id, article))
+        } else {
+            process(res, remove_bookmark, (logged_id, article))
+        }
+    }
+}
+
+pub fn bookmark_handler(req: Request, res: Response, c: Captures) {
+    bookmark_route_handler(req, res, c, true)
+}
+
+pub fn unbookmark_handler(req: Request, res: Response, c: Captures) {
+    bookmark_route_handler(req, res, c, false)
+}
+
+fn bookmarked_articles_result(_: ArticlesResult) {}
+
+/// The user's bookmarked articles, newest bookmark first. Articles that went out of
+/// sight since, deleted or unpublished, are left out by the query, before paging.
+#[cfg(feature = "diesel")]
+fn get_bookmarked_articles(params: (i32, i64, i64)) -> Vec<Article> {
+    use schema::{articles, bookmarks};
+
+    let (user_id, offset, limit) = params;
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+
+    let mut query = bookmarks::table
+        .inner_join(articles::table)
+        .filter(bookmarks::userid.eq(user_id))
+        .filter(articles::deletedat.is_null())
+        .select(articles::all_columns)
+        .into_boxed();
+
+    // what can_view lets through
+    if get_role(user_id) < Role::Moderator {
+        query = query.filter(
+            articles::author.eq(user_id).or(
+                articles::hidden.eq(false)
+                    .and(articles::status.eq("published"))
+                    .and(articles::publishat.is_null().or(articles::publishat.le(Some(now)))),
+            ),
+        );
+    }
+
+    query
+        .order(bookmarks::createdat.desc())
+        .offset(offset)
+        .limit(limit)
+        .load(&connection)
+        .expect("Error loading bookmarks")
+}
+
+pub fn list_bookmarks_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let (offset, limit) = parse_paging(caps[0].splitn(2, '?').nth(1).unwrap_or(""));
+
+    #[cfg(feature = "diesel")]
+    process_container(res, bookmarked_articles_result, get_bookmarked_articles, (logged_id, offset, limit));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn bookmark_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}/bookmark", slug);
+
+    // bookmarking twice keeps one bookmark
+    for _ in 0..2 {
+        let mut res = client
+            .post(&url)
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        let result: BookmarkResult = serde_json::from_str(&buffer).unwrap();
+        assert!(result.bookmark.bookmarked);
+    }
+
+    let mut res = client
+        .get("http://localhost:6767/api/user/bookmarks?limit=5")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let bookmarked: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(bookmarked.articles.len(), 1);
+    assert_eq!(bookmarked.articles[0].slug, slug);
+
+    // bookmarks aren't likes
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles/{}", slug))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: ArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.article.favoritesCount, 0);
+
+    let res = client
+        .delete(&url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut res = client
+        .get("http://localhost:6767/api/user/bookmarks")
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let bookmarked: ArticlesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(bookmarked.articles.len(), 0);
+}
@@ -51,6 +51,9 @@ fn get_simple_comment_from_row(row: tiberius::query::QueryRow) -> Option<Comment
         bio: bio.map(|s| s.to_string()),
         image: image.map(|s| s.to_string()),
         following: following,
+        followersCount: None,
+        followingCount: None,
+        articlesCount: None,
     };
     let comment = Comment {
         id: id,
@@ -72,8 +75,9 @@ fn get_comment_from_row(row: tiberius::query::QueryRow) -> Option<CommentResult>
 }
 
 #[cfg(feature = "diesel")]
-fn add_comment(comment: NewComment) -> Option<CommentResult> {
+fn add_comment(params: (NewComment, i32)) -> Option<CommentResult> {
     use schema::comments;
+    let (comment, article_author) = params;
     let connection = establish_connection();
 
     let comment_result: Comment = diesel::insert(&comment)
@@ -81,13 +85,34 @@ fn add_comment(comment: NewComment) -> Option<CommentResult> {
         .get_result(&connection)
         .expect("Error saving new post");    
 
+    notify(article_author, comment_result.author, NotificationKind::Comment, Some(comment_result.articleid), Some(comment_result.id));
+    publish_event(Topic::Article(comment_result.articleid), comment_result.author, "comment", &comment_result);
+    dispatch_webhook_event("comment.added", vec![article_author, comment_result.author], &comment_result);
+
     Some(CommentResult { comment: comment_result,} )
 }
 
 pub fn add_comment_handler(req: Request, res: Response, c: Captures) {
     let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let res = match require_verified(res, logged_id, Restricted::Comments) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let raw_comment: AddComment = match serde_json::from_str(&body) {
+        Ok(raw_comment) => raw_comment,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("comment", "can't be read")),
+    };
+
+    let errors = raw_comment.comment.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
 
-    let raw_comment: AddComment = serde_json::from_str(&body).unwrap();
     let comment_body: &str = &raw_comment.comment.body;
     println!("comment_body: {}", comment_body);
 
@@ -102,7 +127,15 @@ pub fn add_comment_handler(req: Request, res: Response, c: Captures) {
          use chrono::prelude::*;
          let utc: DateTime<Utc> = Utc::now();
 
+         match find_article(slug) {
+             Some(ref article) if can_view(article, logged_id) => {}
+             _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+         }
          let article = get_advanced_article(slug).unwrap().article;
+
+         if is_blocked(article.author, logged_id) {
+             return send_errors(res, StatusCode::Forbidden, ValidationErrors::with("article", "can't be commented on"));
+         }
          
          let comment = NewComment {
              createdat : utc.naive_utc(),
@@ -112,7 +145,7 @@ pub fn add_comment_handler(req: Request, res: Response, c: Captures) {
              author : logged_id,
          };
 
-         process(res, add_comment, comment)
+         process(res, add_comment, (comment, article.author))
      }
 
     #[cfg(feature = "tiberius")]
@@ -130,17 +163,17 @@ pub fn add_comment_handler(req: Request, res: Response, c: Captures) {
 }
 
 fn delete_comment(comment_to_del: Comment) -> Option<bool> {
-    use schema::comments::dsl::*;
-    let connection = establish_connection();
-
-    diesel::delete(comments.filter(id.eq(comment_to_del.id)))
-        .execute(&connection).expect("Failed to delete a comment");
+    soft_delete_comment(comment_to_del.id);
     None
 }
 
 
 pub fn delete_comment_handler(req: Request, res: Response, c: Captures) {
     let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     let caps = c.unwrap();
     let url_params = &caps[0];
@@ -153,10 +186,18 @@ pub fn delete_comment_handler(req: Request, res: Response, c: Captures) {
 
         let connection = establish_connection();
 
-        let comment_to_del: Comment = comments
-            .filter(id.eq(comment_id.parse::<i32>().unwrap()).and(author.eq(logged_id)))
-            .first(&connection)
-            .unwrap();
+        let comment_to_del: Comment = match comments
+            .filter(id.eq(comment_id.parse::<i32>().unwrap_or(0)))
+            .filter(deletedat.is_null())
+            .first(&connection) {
+            Ok(comment) => comment,
+            Err(_) => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("comment", "not found")),
+        };
+        let owner_id = comment_to_del.author;
+        let res = match require_permission(res, logged_id, Permission::DeleteComment, Some(owner_id)) {
+            Some(res) => res,
+            None => return,
+        };
 
         process(res, delete_comment, comment_to_del)
     }
@@ -177,30 +218,52 @@ pub fn delete_comment_handler(req: Request, res: Response, c: Captures) {
 fn comments_result(_: CommentsResult) {}
 
 #[cfg(feature = "diesel")]
-fn get_comments(url_slug: &str) -> Option<CommentsResult> {
+fn get_comments(params: (&str, i32)) -> Option<CommentsResult> {
+    let (url_slug, viewer_id) = params;
     let connection = establish_connection();
 
     let article: Article = get_article(url_slug);
+    let hidden_authors = get_hidden_authors(viewer_id);
 
     let result : Vec<Comment> = <Comment as BelongingToDsl<&Article>>::belonging_to(&article)
+        .filter(schema::comments::hidden.eq(false))
+        .filter(schema::comments::deletedat.is_null())
         .load::<Comment>(&connection)
-        .expect("Error loading comments");
+        .expect("Error loading comments")
+        .into_iter()
+        .filter(|comment| !hidden_authors.contains(&comment.author))
+        .collect();
 
     Some(CommentsResult { comments: result,})
 }
 
+#[cfg(feature = "diesel")]
+fn get_rendered_comments(params: (&str, i32)) -> Option<RenderedCommentsResult> {
+    get_comments(params).map(|result| RenderedCommentsResult { comments: render_comments(result.comments) })
+}
+
 pub fn get_comments_handler(req: Request, res: Response, c: Captures) {
+    let render = wants_html(&req);
     let (_, logged_id) = prepare_parameters(req);
 
     let caps = c.unwrap();
-    let slug = &caps[0].replace("/api/articles/", "").replace(
+    let url_path = caps[0].split('?').next().unwrap_or("").to_string();
+    let slug = &url_path.replace("/api/articles/", "").replace(
         "/comments",
         "",
     );
     println!("get_comments_handler slug: '{}'", slug);
 
     #[cfg(feature = "diesel")] {
-        process(res, get_comments, &slug)
+        match find_article(slug) {
+            Some(ref article) if can_view(article, logged_id) => {}
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        }
+        if render {
+            process(res, get_rendered_comments, (slug.as_str(), logged_id))
+        } else {
+            process(res, get_comments, (slug.as_str(), logged_id))
+        }
     }
 
     #[cfg(feature = "tiberius")]
@@ -311,3 +374,42 @@ fn delete_comment_test() {
     let comments: CommentsResult = serde_json::from_str(&buffer).unwrap();
     assert_eq!(comments.comments.len(), 0);
 }
+
+#[cfg(test)]
+#[test]
+fn moderator_delete_comment_test() {
+    let client = Client::new();
+
+    let (jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}/comments", slug);
+
+    let mut res = client
+        .post(&url)
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"comment": {"body": "His name was my name too."}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let comment_result: CommentResult = serde_json::from_str(&buffer).unwrap();
+
+    let url2 = format!("{}/{}", url, comment_result.comment.id);
+    let (moderator_name, moderator_email) = register_jacob();
+    let moderator_jwt = login_jacob(moderator_email, user::JACOB_PASSWORD.to_string());
+
+    let res = client
+        .delete(&url2)
+        .header(Authorization(Bearer { token: moderator_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    set_role(&moderator_name, Role::Moderator);
+
+    let res = client
+        .delete(&url2)
+        .header(Authorization(Bearer { token: moderator_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
@@ -0,0 +1,495 @@
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+
+use hyper::header::{CacheControl, CacheDirective};
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+lazy_static! {
+    static ref HEARTBEAT_SECONDS : u64 = get_config()
+        .events
+        .and_then(|c| c.heartbeat_seconds)
+        .unwrap_or(15);
+    static ref HISTORY_SIZE : usize = get_config()
+        .events
+        .and_then(|c| c.history_size)
+        .unwrap_or(500);
+    /// Every open stream holds on to a server thread for as long as it's open.
+    pub static ref SERVER_THREADS : usize = get_config()
+        .events
+        .and_then(|c| c.server_threads)
+        .unwrap_or(64);
+    static ref EVENT_BUS : Mutex<EventBus> = Mutex::new(EventBus::new(*HISTORY_SIZE));
+    /// Half the threads by default, the other half keeps answering the rest of the API.
+    static ref MAX_STREAMS : usize = get_config()
+        .events
+        .and_then(|c| c.max_streams)
+        .unwrap_or(*SERVER_THREADS / 2);
+    static ref MAX_STREAMS_PER_CLIENT : usize = get_config()
+        .events
+        .and_then(|c| c.max_streams_per_client)
+        .unwrap_or(4);
+    static ref STREAM_SLOTS : Mutex<StreamSlots> = Mutex::new(StreamSlots::new(*MAX_STREAMS, *MAX_STREAMS_PER_CLIENT));
+    static ref SCHEDULE_POLL_SECONDS : u64 = get_config()
+        .events
+        .and_then(|c| c.schedule_poll_seconds)
+        .unwrap_or(5);
+    /// Scheduled articles coming out up to here have been announced by the schedule thread.
+    static ref SCHEDULE_CHECKED_UNTIL : Mutex<NaiveDateTime> = Mutex::new(Utc::now().naive_utc());
+}
+
+/// Who an event is for: everyone watching an article, or a single user.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Topic {
+    Article(i32),
+    User(i32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Event {
+    pub id: u64,
+    pub topic: Topic,
+    /// The user who caused the event, so readers who blocked or muted them can skip it.
+    pub actor: i32,
+    pub name: &'static str,
+    pub data: String,
+}
+
+struct Subscriber {
+    id: u64,
+    topics: Vec<Topic>,
+    sender: Sender<Event>,
+}
+
+/// Hands events over to whoever is listening on their topic, and keeps the latest ones
+/// around for clients coming back with a `Last-Event-ID`. Ids only hold for the lifetime
+/// of the process.
+pub struct EventBus {
+    next_event_id: u64,
+    next_subscriber_id: u64,
+    history_size: usize,
+    history: VecDeque<Event>,
+    subscribers: Vec<Subscriber>,
+}
+
+impl EventBus {
+    pub fn new(history_size: usize) -> EventBus {
+        EventBus {
+            next_event_id: 1,
+            next_subscriber_id: 1,
+            history_size: history_size,
+            history: VecDeque::new(),
+            subscribers: Vec::new(),
+        }
+    }
+
+    pub fn publish(&mut self, topic: Topic, actor: i32, name: &'static str, data: String) -> u64 {
+        let event = Event {
+            id: self.next_event_id,
+            topic: topic,
+            actor: actor,
+            name: name,
+            data: data,
+        };
+        self.next_event_id += 1;
+
+        // a failed send means the stream went away without unsubscribing
+        self.subscribers.retain(|subscriber| {
+            !subscriber.topics.contains(&topic) || subscriber.sender.send(event.clone()).is_ok()
+        });
+
+        if self.history.len() >= self.history_size {
+            self.history.pop_front();
+        }
+        self.history.push_back(event);
+        self.next_event_id - 1
+    }
+
+    /// Starts listening on `topics`, along with the kept events past `last_event_id`.
+    pub fn subscribe(&mut self, topics: Vec<Topic>, last_event_id: Option<u64>) -> (u64, Vec<Event>, Receiver<Event>) {
+        let missed: Vec<Event> = match last_event_id {
+            Some(last_event_id) => self.history
+                .iter()
+                .filter(|event| event.id > last_event_id && topics.contains(&event.topic))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let (sender, receiver) = channel();
+        let subscriber_id = self.next_subscriber_id;
+        self.next_subscriber_id += 1;
+        self.subscribers.push(Subscriber {
+            id: subscriber_id,
+            topics: topics,
+            sender: sender,
+        });
+
+        (subscriber_id, missed, receiver)
+    }
+
+    pub fn unsubscribe(&mut self, subscriber_id: u64) {
+        self.subscribers.retain(|subscriber| subscriber.id != subscriber_id);
+    }
+}
+
+pub fn publish_event<T: serde::Serialize>(topic: Topic, actor: i32, name: &'static str, data: &T) {
+    let data = serde_json::to_string(data).unwrap();
+    EVENT_BUS.lock().unwrap().publish(topic, actor, name, data);
+}
+
+/// A new article goes to the live feeds of its author's followers, once it's out. One whose
+/// `publishAt` is still ahead of the schedule thread is left for that thread to announce.
+#[cfg(feature = "diesel")]
+pub fn publish_new_article(article: &Article, item: &AdvancedArticle) {
+    if article.hidden || !is_published(article, Utc::now().naive_utc()) {
+        return;
+    }
+
+    let checked_until = *SCHEDULE_CHECKED_UNTIL.lock().unwrap();
+    if article.publishAt.map_or(true, |publish_at| publish_at <= checked_until) {
+        if let Some(connection) = try_establish_connection() {
+            if let Err(error) = send_to_followers(&connection, article, item) {
+                println!("Error announcing article {}: {}", article.id, error);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn send_to_followers(connection: &PgConnection, article: &Article, item: &AdvancedArticle) -> QueryResult<()> {
+    use schema::followings::dsl::*;
+
+    let follower_ids: Vec<i32> = followings
+        .filter(followingid.eq(article.author))
+        .select(followerid)
+        .load(connection)?;
+
+    for follower_id in follower_ids {
+        publish_event(Topic::User(follower_id), article.author, "article", item);
+    }
+    Ok(())
+}
+
+/// Announces the articles whose `publishAt` came since the last check. The check moves on
+/// before the query runs: an article published before that is in the query, one published
+/// after sees the new end and announces itself. When the query fails the check moves back,
+/// so the next round covers the same time again.
+#[cfg(feature = "diesel")]
+fn announce_scheduled_articles() {
+    use schema::articles::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let checked_until = ::std::mem::replace(&mut *SCHEDULE_CHECKED_UNTIL.lock().unwrap(), now);
+
+    let connection = match try_establish_connection() {
+        Some(connection) => connection,
+        None => {
+            *SCHEDULE_CHECKED_UNTIL.lock().unwrap() = checked_until;
+            return;
+        }
+    };
+    let scheduled: Vec<Article> = match articles
+        .filter(status.eq("published"))
+        .filter(publishat.gt(Some(checked_until)))
+        .filter(publishat.le(Some(now)))
+        .filter(hidden.eq(false))
+        .filter(deletedat.is_null())
+        .load(&connection)
+    {
+        Ok(scheduled) => scheduled,
+        Err(error) => {
+            println!("Error loading scheduled articles: {}", error);
+            *SCHEDULE_CHECKED_UNTIL.lock().unwrap() = checked_until;
+            return;
+        }
+    };
+
+    for article in scheduled {
+        if let Some(result) = get_advanced_article(&article.slug) {
+            if let Err(error) = send_to_followers(&connection, &article, &result.article) {
+                println!("Error announcing article {}: {}", article.id, error);
+            }
+        }
+    }
+}
+
+/// Runs `announce_scheduled_articles` every `schedule_poll_seconds` for as long as the server is up.
+#[cfg(feature = "diesel")]
+pub fn start_schedule_thread() {
+    let interval = ::std::time::Duration::from_secs(*SCHEDULE_POLL_SECONDS);
+    ::std::thread::spawn(move || loop {
+        ::std::thread::sleep(interval);
+        announce_scheduled_articles();
+    });
+}
+
+/// Who holds a stream open: the logged user, or the address of an anonymous one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StreamClient {
+    User(i32),
+    Address(IpAddr),
+}
+
+/// Counts open streams, in all and per client, so they can't take up every server thread.
+pub struct StreamSlots {
+    max_streams: usize,
+    max_per_client: usize,
+    open: usize,
+    per_client: HashMap<StreamClient, usize>,
+}
+
+impl StreamSlots {
+    pub fn new(max_streams: usize, max_per_client: usize) -> StreamSlots {
+        StreamSlots {
+            max_streams: max_streams,
+            max_per_client: max_per_client,
+            open: 0,
+            per_client: HashMap::new(),
+        }
+    }
+
+    pub fn acquire(&mut self, client: StreamClient) -> bool {
+        let client_open = self.per_client.get(&client).cloned().unwrap_or(0);
+        if self.open >= self.max_streams || client_open >= self.max_per_client {
+            return false;
+        }
+        self.open += 1;
+        self.per_client.insert(client, client_open + 1);
+        true
+    }
+
+    pub fn release(&mut self, client: StreamClient) {
+        self.open = self.open.saturating_sub(1);
+        let client_open = self.per_client.remove(&client).unwrap_or(0);
+        if client_open > 1 {
+            self.per_client.insert(client, client_open - 1);
+        }
+    }
+}
+
+/// A taken slot, given back when the stream ends however it ends.
+struct StreamSlot(StreamClient);
+
+impl Drop for StreamSlot {
+    fn drop(&mut self) {
+        STREAM_SLOTS.lock().unwrap().release(self.0);
+    }
+}
+
+fn format_event(event: &Event) -> String {
+    format!("id: {}\nevent: {}\ndata: {}\n\n", event.id, event.name, event.data)
+}
+
+fn last_event_id(req: &Request) -> Option<u64> {
+    req.headers
+        .get_raw("Last-Event-ID")
+        .and_then(|values| values.first())
+        .and_then(|value| String::from_utf8_lossy(value).trim().parse::<u64>().ok())
+}
+
+/// Writes events on `topics` as they come, with a comment line every `heartbeat_seconds` so
+/// proxies keep the connection open and a closed one gets noticed.
+fn stream_events(mut res: Response, viewer_id: i32, client: StreamClient, topics: Vec<Topic>, last_event_id: Option<u64>) {
+    if !STREAM_SLOTS.lock().unwrap().acquire(client) {
+        res.headers_mut().set_raw("Retry-After", vec![HEARTBEAT_SECONDS.to_string().into_bytes()]);
+        let errors = ValidationErrors::with("events", "too many open streams, try again later");
+        return send_errors(res, StatusCode::ServiceUnavailable, errors);
+    }
+    let _slot = StreamSlot(client);
+
+    #[cfg(feature = "diesel")]
+    let hidden_authors = get_hidden_authors(viewer_id);
+    #[cfg(not(feature = "diesel"))]
+    let hidden_authors: Vec<i32> = Vec::new();
+
+    let (subscriber_id, missed, receiver) = EVENT_BUS.lock().unwrap().subscribe(topics, last_event_id);
+
+    res.headers_mut().set(AccessControlAllowOrigin::Any);
+    res.headers_mut().set(AccessControlAllowHeaders(vec![
+        UniCase("content-type".to_owned()),
+        UniCase("authorization".to_owned()),
+        UniCase("last-event-id".to_owned()),
+    ]));
+    res.headers_mut().set(ContentType(Mime(TopLevel::Text, SubLevel::Ext("event-stream".to_owned()), vec![])));
+    res.headers_mut().set(CacheControl(vec![CacheDirective::NoCache]));
+
+    if let Ok(mut res) = res.start() {
+        let heartbeat = ::std::time::Duration::from_secs(*HEARTBEAT_SECONDS);
+        let mut pending: VecDeque<Event> = missed.into_iter().collect();
+
+        loop {
+            let chunk = match pending.pop_front().map(Ok).unwrap_or_else(|| receiver.recv_timeout(heartbeat)) {
+                Ok(ref event) if hidden_authors.contains(&event.actor) => continue,
+                Ok(event) => format_event(&event),
+                Err(RecvTimeoutError::Timeout) => ": heartbeat\n\n".to_string(),
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+            if res.write_all(chunk.as_bytes()).and_then(|_| res.flush()).is_err() {
+                break;
+            }
+        }
+        let _ = res.end();
+    }
+
+    EVENT_BUS.lock().unwrap().unsubscribe(subscriber_id);
+}
+
+/// New comments on an article.
+pub fn article_events_handler(req: Request, res: Response, c: Captures) {
+    let last_event_id = last_event_id(&req);
+    let address = client_address(&req);
+    let (_, logged_id) = prepare_parameters(req);
+    let client = if logged_id > 0 { StreamClient::User(logged_id) } else { StreamClient::Address(address) };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/events", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let article = match find_article(slug) {
+            Some(ref article) if can_view(article, logged_id) => article.clone(),
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+
+        stream_events(res, logged_id, client, vec![Topic::Article(article.id)], last_event_id);
+    }
+}
+
+/// Notifications and new articles in the feed of the logged user.
+pub fn user_events_handler(req: Request, res: Response, _: Captures) {
+    let last_event_id = last_event_id(&req);
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    stream_events(res, logged_id, StreamClient::User(logged_id), vec![Topic::User(logged_id)], last_event_id);
+}
+
+#[cfg(test)]
+#[test]
+fn event_bus_test() {
+    let mut bus = EventBus::new(2);
+    let (_, missed, receiver) = bus.subscribe(vec![Topic::Article(1)], None);
+    assert!(missed.is_empty());
+
+    bus.publish(Topic::Article(2), 1, "comment", "{}".to_string());
+    let first = bus.publish(Topic::Article(1), 1, "comment", "{\"id\":1}".to_string());
+    bus.publish(Topic::Article(1), 1, "comment", "{\"id\":2}".to_string());
+    let received: Vec<Event> = receiver.try_iter().collect();
+    assert_eq!(received.len(), 2);
+    assert_eq!(received[0].id, first);
+    assert_eq!(format_event(&received[0]), format!("id: {}\nevent: comment\ndata: {{\"id\":1}}\n\n", first));
+
+    // coming back after the first event only replays the second
+    let (subscriber_id, missed, _) = bus.subscribe(vec![Topic::Article(1)], Some(first));
+    assert_eq!(missed.len(), 1);
+    assert_eq!(missed[0].data, "{\"id\":2}");
+
+    bus.unsubscribe(subscriber_id);
+    assert_eq!(bus.subscribers.len(), 1);
+}
+
+#[cfg(test)]
+#[test]
+fn stream_slots_test() {
+    let mut slots = StreamSlots::new(3, 2);
+    let jake = StreamClient::User(1);
+    let anonymous = StreamClient::Address("203.0.113.7".parse().unwrap());
+
+    assert!(slots.acquire(jake));
+    assert!(slots.acquire(jake));
+    assert!(!slots.acquire(jake));
+    assert!(slots.acquire(anonymous));
+    assert!(!slots.acquire(StreamClient::User(2)));
+
+    slots.release(jake);
+    assert!(slots.acquire(StreamClient::User(2)));
+    slots.release(anonymous);
+    assert!(slots.acquire(jake));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn comment_events_test() {
+    use std::io::BufReader;
+
+    let mut client = Client::new();
+    client.set_read_timeout(Some(::std::time::Duration::from_secs(30)));
+    let (jwt, slug, _) = login_create_article(false);
+
+    let res = client
+        .get(&format!("http://localhost:6767/api/articles/{}/events", slug))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res_comment = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments", slug))
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"comment": {"body": "Thank you so much!"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res_comment.status, hyper::Ok);
+
+    let mut reader = BufReader::new(res);
+    let mut lines: Vec<String> = Vec::new();
+    while lines.len() < 3 {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if !line.starts_with(':') && line.trim() != "" {
+            lines.push(line.trim().to_string());
+        }
+    }
+    assert!(lines[0].starts_with("id: "));
+    assert_eq!(lines[1], "event: comment");
+    assert!(lines[2].contains("Thank you so much!"));
+}
+
+#[cfg(test)]
+#[test]
+fn published_draft_events_test() {
+    use std::io::BufReader;
+
+    let mut client = Client::new();
+    client.set_read_timeout(Some(::std::time::Duration::from_secs(30)));
+    let (_, _, jwt) = user::follow_jacob();
+
+    let res = client
+        .get("http://localhost:6767/api/events")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let slug = create_unpublished_article(&jwt, r#""status": "draft""#);
+    let res_publish = client
+        .post(&format!("http://localhost:6767/api/articles/{}/publish", slug))
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res_publish.status, hyper::Ok);
+
+    // the draft itself went out to nobody, publishing it is what announces it
+    let mut reader = BufReader::new(res);
+    let mut event_name = String::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        if line.starts_with("event: ") {
+            event_name = line.trim().to_string();
+        } else if line.starts_with("data: ") && event_name == "event: article" {
+            assert!(line.contains(&slug));
+            break;
+        }
+    }
+}
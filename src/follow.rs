@@ -0,0 +1,240 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ProfilesResult {
+    pub profiles: Vec<Profile>,
+}
+
+impl Container<Profile> for ProfilesResult {
+    fn create_new_with_items(profiles: Vec<Profile>) -> ProfilesResult {
+        ProfilesResult { profiles: profiles }
+    }
+}
+
+/// Which of `user_ids` `follower_id` follows, nobody's for 0.
+#[cfg(feature = "diesel")]
+pub fn followed_among(connection: &PgConnection, follower_id: i32, user_ids: &[i32]) -> Vec<i32> {
+    use diesel::expression::dsl::any;
+    use schema::followings::dsl::*;
+
+    if follower_id <= 0 || user_ids.is_empty() {
+        return Vec::new();
+    }
+    followings
+        .filter(followerid.eq(follower_id))
+        .filter(followingid.eq(any(user_ids.to_vec())))
+        .select(followingid)
+        .load(connection)
+        .expect("Error loading followings")
+}
+
+/// Followers, followed users and articles of a user. Only articles anyone can read are
+/// counted, drafts, scheduled, hidden and deleted ones are left out.
+#[cfg(feature = "diesel")]
+pub fn profile_counts(connection: &PgConnection, user_id: i32) -> (i64, i64, i64) {
+    use schema::{articles, followings};
+
+    let followers_count: i64 = followings::table
+        .filter(followings::followingid.eq(user_id))
+        .count()
+        .get_result(connection)
+        .expect("Error counting followers");
+    let following_count: i64 = followings::table
+        .filter(followings::followerid.eq(user_id))
+        .count()
+        .get_result(connection)
+        .expect("Error counting followed users");
+
+    let now = Utc::now().naive_utc();
+    let articles_count: i64 = articles::table
+        .filter(articles::author.eq(user_id))
+        .filter(articles::deletedat.is_null())
+        .filter(articles::hidden.eq(false))
+        .filter(articles::status.eq("published"))
+        .filter(articles::publishat.is_null().or(articles::publishat.le(Some(now))))
+        .count()
+        .get_result(connection)
+        .expect("Error counting articles");
+
+    (followers_count, following_count, articles_count)
+}
+
+#[cfg(feature = "diesel")]
+fn profile_with_counts(connection: &PgConnection, user: User, following: bool) -> Profile {
+    let (followers_count, following_count, articles_count) = profile_counts(connection, user.id);
+
+    Profile {
+        following: following,
+        username: user.username,
+        bio: user.bio,
+        image: user.image,
+        followersCount: Some(followers_count),
+        followingCount: Some(following_count),
+        articlesCount: Some(articles_count),
+    }
+}
+
+/// `user` as seen by `viewer_id`, 0 when nobody is logged in.
+#[cfg(feature = "diesel")]
+pub fn profile_for(user: User, viewer_id: i32) -> Profile {
+    let connection = establish_connection();
+    let following = followed_among(&connection, viewer_id, &[user.id]).contains(&user.id);
+    profile_with_counts(&connection, user, following)
+}
+
+/// `users` as seen by `viewer_id`, all on one connection with `following` looked up at once.
+#[cfg(feature = "diesel")]
+pub fn profiles_for(users: Vec<User>, viewer_id: i32) -> Vec<Profile> {
+    let connection = establish_connection();
+    let user_ids: Vec<i32> = users.iter().map(|user| user.id).collect();
+    let followed = followed_among(&connection, viewer_id, &user_ids);
+
+    users
+        .into_iter()
+        .map(|user| {
+            let following = followed.contains(&user.id);
+            profile_with_counts(&connection, user, following)
+        })
+        .collect()
+}
+
+#[derive(Debug)]
+pub struct FollowListParams {
+    pub user_id: i32,
+    pub viewer_id: i32,
+    pub followers: bool,
+    pub offset: i64,
+    pub limit: i64,
+}
+
+fn profiles_result(_: ProfilesResult) {}
+
+/// Followers or followed users, most recent follow first.
+#[cfg(feature = "diesel")]
+fn get_follow_list(params: FollowListParams) -> Vec<Profile> {
+    use diesel::expression::dsl::any;
+    use schema::{followings, users};
+
+    let connection = establish_connection();
+
+    let query = followings::table
+        .order(followings::id.desc())
+        .offset(params.offset)
+        .limit(params.limit);
+    let user_ids: Vec<i32> = if params.followers {
+        query
+            .filter(followings::followingid.eq(params.user_id))
+            .select(followings::followerid)
+            .load(&connection)
+    } else {
+        query
+            .filter(followings::followerid.eq(params.user_id))
+            .select(followings::followingid)
+            .load(&connection)
+    }.expect("Error loading followings");
+
+    let mut listed: Vec<User> = users::table
+        .filter(users::id.eq(any(user_ids.clone())))
+        .load(&connection)
+        .expect("Error loading users");
+    listed.sort_by_key(|user| user_ids.iter().position(|&user_id| user_id == user.id));
+
+    profiles_for(listed, params.viewer_id)
+}
+
+fn follow_list_handler(req: Request, res: Response, c: Captures, followers: bool) {
+    let (_, logged_id) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let suffix = if followers { "/followers" } else { "/following" };
+    let path = caps[0].replace("/api/profiles/", "");
+    let mut path_and_query = path.splitn(2, '?');
+    let user_name = path_and_query.next().unwrap_or("").replace(suffix, "");
+    let (offset, limit) = parse_paging(path_and_query.next().unwrap_or(""));
+
+    #[cfg(feature = "diesel")]
+    {
+        let user = match get_user_by_name(&user_name) {
+            Some(user) => user,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found")),
+        };
+
+        let params = FollowListParams {
+            user_id: user.id,
+            viewer_id: logged_id,
+            followers: followers,
+            offset: offset,
+            limit: limit,
+        };
+        process_container(res, profiles_result, get_follow_list, params);
+    }
+}
+
+pub fn get_followers_handler(req: Request, res: Response, c: Captures) {
+    follow_list_handler(req, res, c, true)
+}
+
+pub fn get_following_handler(req: Request, res: Response, c: Captures) {
+    follow_list_handler(req, res, c, false)
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn follow_lists_test() {
+    let client = Client::new();
+
+    let (followed_name, followed_email) = register_jacob();
+    let followed_jwt = login_jacob(followed_email, user::JACOB_PASSWORD.to_string());
+    let (follower_name, follower_email) = register_jacob();
+    let follower_jwt = login_jacob(follower_email, user::JACOB_PASSWORD.to_string());
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/profiles/{}/follow", followed_name))
+        .header(Authorization(Bearer { token: follower_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // seen by the followed user, who doesn't follow back
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/profiles/{}/followers?limit=5", followed_name))
+        .header(Authorization(Bearer { token: followed_jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    let result: ProfilesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.profiles.len(), 1);
+    assert_eq!(result.profiles[0].username, follower_name);
+    assert_eq!(result.profiles[0].following, false);
+    assert_eq!(result.profiles[0].followingCount, Some(1));
+
+    // seen by the follower
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/profiles/{}/following", follower_name))
+        .header(Authorization(Bearer { token: follower_jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: ProfilesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.profiles.len(), 1);
+    assert_eq!(result.profiles[0].username, followed_name);
+    assert_eq!(result.profiles[0].following, true);
+    assert_eq!(result.profiles[0].followersCount, Some(1));
+    assert_eq!(result.profiles[0].articlesCount, Some(0));
+
+    let res = client
+        .get("http://localhost:6767/api/profiles/nobody-by-that-name/followers")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::server::Request;
+
+use super::*;
+
+/// How many failed logins are tolerated before backing off, see the [login] section.
+pub struct ThrottlePolicy {
+    pub account_attempts: u32,
+    pub address_attempts: u32,
+    pub base_delay_seconds: u64,
+    pub max_delay_seconds: u64,
+    pub forget_after_seconds: u64,
+}
+
+lazy_static! {
+    /// Only behind a proxy that sets `X-Forwarded-For` itself, otherwise clients could pick any address.
+    static ref TRUST_FORWARDED_FOR : bool = get_config()
+        .login
+        .and_then(|c| c.trust_forwarded_for)
+        .unwrap_or(false);
+}
+
+fn get_throttle_policy() -> ThrottlePolicy {
+    let config = get_config().login;
+    let config = config.as_ref();
+
+    ThrottlePolicy {
+        account_attempts: config.and_then(|c| c.max_failed_attempts).unwrap_or(5),
+        address_attempts: config.and_then(|c| c.max_failed_attempts_per_ip).unwrap_or(20),
+        base_delay_seconds: config.and_then(|c| c.backoff_base_seconds).unwrap_or(1),
+        max_delay_seconds: config.and_then(|c| c.max_lockout_seconds).unwrap_or(15 * 60),
+        forget_after_seconds: config.and_then(|c| c.forget_after_minutes).unwrap_or(60) * 60,
+    }
+}
+
+struct Failures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Failed attempts per key, each one past `allowed` doubles the lockout.
+struct FailureCounter<K: Hash + Eq> {
+    failures: HashMap<K, Failures>,
+}
+
+impl<K: Hash + Eq> FailureCounter<K> {
+    fn new() -> FailureCounter<K> {
+        FailureCounter { failures: HashMap::new() }
+    }
+
+    fn locked_for(&self, key: &K, now: Instant) -> Option<Duration> {
+        self.failures
+            .get(key)
+            .and_then(|f| f.locked_until)
+            .and_then(|until| if until > now { Some(until - now) } else { None })
+    }
+
+    fn fail(&mut self, key: K, allowed: u32, policy: &ThrottlePolicy, now: Instant) {
+        let forget_after = Duration::from_secs(policy.forget_after_seconds);
+        let failures = self.failures.entry(key).or_insert(Failures {
+            count: 0,
+            last: now,
+            locked_until: None,
+        });
+
+        if now - failures.last > forget_after {
+            failures.count = 0;
+        }
+        failures.count += 1;
+        failures.last = now;
+
+        if failures.count > allowed {
+            let doublings = ::std::cmp::min(failures.count - allowed - 1, 32);
+            let delay = policy
+                .base_delay_seconds
+                .saturating_mul(1u64 << doublings)
+                .min(policy.max_delay_seconds);
+            failures.locked_until = Some(now + Duration::from_secs(delay));
+        }
+    }
+
+    fn reset(&mut self, key: &K) {
+        self.failures.remove(key);
+    }
+
+    fn forget_stale(&mut self, policy: &ThrottlePolicy, now: Instant) {
+        let forget_after = Duration::from_secs(policy.forget_after_seconds);
+        self.failures.retain(|_, f| {
+            now - f.last <= forget_after || f.locked_until.map_or(false, |until| until > now)
+        });
+    }
+}
+
+/// Failed logins per account email and per client address. Kept in memory, so
+/// a restart lifts all lockouts.
+pub struct LoginThrottle {
+    policy: ThrottlePolicy,
+    accounts: FailureCounter<String>,
+    addresses: FailureCounter<IpAddr>,
+}
+
+impl LoginThrottle {
+    pub fn new(policy: ThrottlePolicy) -> LoginThrottle {
+        LoginThrottle {
+            policy: policy,
+            accounts: FailureCounter::new(),
+            addresses: FailureCounter::new(),
+        }
+    }
+
+    /// Seconds until the account or the address may try again, if either is locked.
+    pub fn retry_after(&self, email: &str, address: IpAddr, now: Instant) -> Option<u64> {
+        let account = self.accounts.locked_for(&account_key(email), now);
+        let address = self.addresses.locked_for(&address, now);
+
+        account.into_iter().chain(address).max().map(|wait| {
+            // round up, a client retrying after exactly that many seconds must get through
+            wait.as_secs() + if wait.subsec_nanos() > 0 { 1 } else { 0 }
+        })
+    }
+
+    pub fn fail(&mut self, email: &str, address: IpAddr, now: Instant) {
+        self.accounts.forget_stale(&self.policy, now);
+        self.addresses.forget_stale(&self.policy, now);
+
+        let (account_attempts, address_attempts) = (self.policy.account_attempts, self.policy.address_attempts);
+        self.accounts.fail(account_key(email), account_attempts, &self.policy, now);
+        self.addresses.fail(address, address_attempts, &self.policy, now);
+    }
+
+    /// Only the account starts over, logging into an account of their own mustn't let a client
+    /// clear the failures it collected guessing at others.
+    pub fn succeed(&mut self, email: &str) {
+        self.accounts.reset(&account_key(email));
+    }
+}
+
+fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+lazy_static! {
+    pub static ref LOGIN_THROTTLE : Mutex<LoginThrottle> = Mutex::new(LoginThrottle::new(get_throttle_policy()));
+}
+
+/// Answers 429 with `Retry-After` when the account or the address is locked, otherwise hands `res` back.
+pub fn require_not_throttled(mut res: Response, email: &str, address: IpAddr) -> Option<Response> {
+    let retry_after = LOGIN_THROTTLE.lock().unwrap().retry_after(email, address, Instant::now());
+
+    match retry_after {
+        Some(seconds) => {
+            res.headers_mut().set_raw("Retry-After", vec![seconds.to_string().into_bytes()]);
+            let errors = ValidationErrors::with(
+                "email or password",
+                &format!("too many failed attempts, try again in {} seconds", seconds),
+            );
+            send_errors(res, StatusCode::TooManyRequests, errors);
+            None
+        }
+        None => Some(res),
+    }
+}
+
+pub fn record_failed_login(email: &str, address: IpAddr) {
+    LOGIN_THROTTLE.lock().unwrap().fail(email, address, Instant::now());
+}
+
+pub fn record_successful_login(email: &str) {
+    LOGIN_THROTTLE.lock().unwrap().succeed(email);
+}
+
+/// The last address the proxy appended, which may come with a port.
+fn forwarded_address(forwarded_for: &str) -> Option<IpAddr> {
+    let last = forwarded_for.split(',').last().unwrap_or("").trim();
+    last.parse::<IpAddr>()
+        .ok()
+        .or_else(|| last.parse::<SocketAddr>().ok().map(|address| address.ip()))
+}
+
+/// The client's address, from `X-Forwarded-For` when `trust_forwarded_for` is on in the [login] section.
+pub fn client_address(req: &Request) -> IpAddr {
+    let forwarded = if *TRUST_FORWARDED_FOR {
+        req.headers
+            .get_raw("X-Forwarded-For")
+            .and_then(|values| values.last())
+            .and_then(|value| forwarded_address(&String::from_utf8_lossy(value)))
+    } else {
+        None
+    };
+    forwarded.unwrap_or(req.remote_addr.ip())
+}
+
+#[cfg(test)]
+fn test_policy() -> ThrottlePolicy {
+    ThrottlePolicy {
+        account_attempts: 3,
+        address_attempts: 5,
+        base_delay_seconds: 2,
+        max_delay_seconds: 10,
+        forget_after_seconds: 60,
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn backoff_test() {
+    let mut throttle = LoginThrottle::new(test_policy());
+    let address: IpAddr = "10.0.0.1".parse().unwrap();
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        throttle.fail("jake@jake.jake", address, now);
+    }
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now), None);
+
+    throttle.fail("Jake@Jake.jake", address, now);
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now), Some(2));
+    throttle.fail("jake@jake.jake", address, now);
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now), Some(4));
+    throttle.fail("jake@jake.jake", address, now);
+    throttle.fail("jake@jake.jake", address, now);
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now), Some(10));
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now + Duration::from_secs(10)), None);
+
+    // the address is locked too by now, for every account
+    assert!(throttle.retry_after("other@jake.jake", address, now).is_some());
+
+    // logging in lifts the account lockout but not the one on the address
+    throttle.succeed("jake@jake.jake");
+    assert!(throttle.retry_after("other@jake.jake", address, now).is_some());
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now + Duration::from_secs(10)), None);
+}
+
+#[cfg(test)]
+#[test]
+fn forwarded_address_test() {
+    assert_eq!(forwarded_address("203.0.113.7"), Some("203.0.113.7".parse().unwrap()));
+    assert_eq!(forwarded_address("10.1.1.1, 203.0.113.7:51234"), Some("203.0.113.7".parse().unwrap()));
+    assert_eq!(forwarded_address("[2001:db8::1]:443"), Some("2001:db8::1".parse().unwrap()));
+    assert_eq!(forwarded_address("unknown"), None);
+}
+
+#[cfg(test)]
+#[test]
+fn forget_old_failures_test() {
+    let mut throttle = LoginThrottle::new(test_policy());
+    let address: IpAddr = "10.0.0.2".parse().unwrap();
+    let now = Instant::now();
+
+    for _ in 0..3 {
+        throttle.fail("jake@jake.jake", address, now);
+    }
+    throttle.fail("jake@jake.jake", address, now + Duration::from_secs(120));
+    assert_eq!(throttle.retry_after("jake@jake.jake", address, now + Duration::from_secs(120)), None);
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn login_lockout_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+
+    let wrong_body = format!(r#"{{"user":{{"email": "{}","password": "wrong password"}}}}"#, email);
+    for _ in 0..get_throttle_policy().account_attempts + 1 {
+        client
+            .post("http://localhost:6767/api/users/login")
+            .body(&wrong_body)
+            .send()
+            .unwrap();
+    }
+
+    // locked out, even with the right password
+    let body = format!(
+        r#"{{"user":{{"email": "{}","password": "{}"}}}}"#,
+        email,
+        user::JACOB_PASSWORD
+    );
+    let mut res = client
+        .post("http://localhost:6767/api/users/login")
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::TooManyRequests);
+
+    let retry_after: u64 = String::from_utf8(res.headers.get_raw("Retry-After").unwrap()[0].to_owned())
+        .unwrap()
+        .parse()
+        .unwrap();
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert!(result.errors.contains_key("email or password"));
+
+    ::std::thread::sleep(Duration::from_secs(retry_after));
+    login_jacob(email, user::JACOB_PASSWORD.to_string());
+}
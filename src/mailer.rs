@@ -0,0 +1,115 @@
+use std::fs::OpenOptions;
+
+use super::*;
+
+#[derive(Debug)]
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Delivers outgoing mail; pick the implementation with `sender` in the [mail] section.
+pub trait Mailer: Send + Sync {
+    fn send(&self, mail: &Mail) -> Result<(), String>;
+}
+
+/// Prints mail to the console.
+pub struct LogMailer;
+
+impl Mailer for LogMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        println!("Mail to: {}, subject: {}\n{}", mail.to, mail.subject, mail.body);
+        Ok(())
+    }
+}
+
+/// Appends mail to a local file, which is what the tests read tokens back from.
+pub struct FileMailer {
+    pub path: PathBuf,
+}
+
+impl Mailer for FileMailer {
+    fn send(&self, mail: &Mail) -> Result<(), String> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| format!("couldn't open {}: {}", self.path.display(), e))?;
+
+        write!(
+            file,
+            "To: {}\nSubject: {}\n\n{}\n----\n",
+            mail.to,
+            mail.subject,
+            mail.body
+        ).map_err(|e| format!("couldn't write {}: {}", self.path.display(), e))
+    }
+}
+
+pub struct MailSettings {
+    pub mailer: Box<Mailer>,
+    pub link_base: String,
+}
+
+fn get_mail_settings() -> MailSettings {
+    let config = get_config().mail;
+    let sender = config.as_ref().and_then(|c| c.sender.to_owned()).unwrap_or("file".to_string());
+    let file = config.as_ref().and_then(|c| c.file.to_owned()).unwrap_or(MAIL_FILE_NAME.to_string());
+
+    let mailer: Box<Mailer> = match sender.as_str() {
+        "log" => Box::new(LogMailer),
+        "file" => Box::new(FileMailer { path: PathBuf::from(file) }),
+        other => panic!("unknown sender {} in [mail] section in {}", other, CONFIG_FILE_NAME),
+    };
+
+    MailSettings {
+        mailer: mailer,
+        link_base: config
+            .as_ref()
+            .and_then(|c| c.link_base.to_owned())
+            .unwrap_or("http://localhost:4100".to_string()),
+    }
+}
+
+pub static MAIL_FILE_NAME: &'static str = r#"mail.log"#;
+
+lazy_static! {
+    pub static ref MAIL : MailSettings = get_mail_settings();
+}
+
+pub fn send_mail(to: &str, subject: &str, body: &str) {
+    let mail = Mail {
+        to: to.to_string(),
+        subject: subject.to_string(),
+        body: body.to_string(),
+    };
+    if let Err(why) = MAIL.mailer.send(&mail) {
+        println!("Failed to send mail to {}: {}", to, why);
+    }
+}
+
+/// Finds the last token sent to `to` right after `marker`, e.g. "/verify?token=".
+/// Some mails are sent after the answer, so it waits a little for them to show up.
+#[cfg(test)]
+pub fn read_token_from_mail(to: &str, marker: &str) -> String {
+    let path = get_config()
+        .mail
+        .and_then(|c| c.file)
+        .unwrap_or(MAIL_FILE_NAME.to_string());
+    let recipient = format!("To: {}\n", to);
+
+    for _ in 0..50 {
+        let mut content = String::new();
+        if let Ok(mut file) = File::open(&path) {
+            file.read_to_string(&mut content).unwrap();
+        }
+
+        if let Some(mail) = content.split("----\n").filter(|m| m.starts_with(&recipient) && m.contains(marker)).last() {
+            let start = mail.find(marker).unwrap() + marker.len();
+            return mail[start..].chars().take_while(|c| c.is_alphanumeric()).collect();
+        }
+        ::std::thread::sleep(::std::time::Duration::from_millis(100));
+    }
+    panic!("No mail to {} with {}", to, marker);
+}
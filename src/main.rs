@@ -3,6 +3,7 @@ extern crate bson;
 
 extern crate iis;
 extern crate hyper;
+extern crate hyper_native_tls;
 
 extern crate serde;
 extern crate serde_json;
@@ -37,6 +38,10 @@ extern crate rand;
 
 extern crate unicase;
 
+extern crate rustc_serialize;
+
+extern crate pulldown_cmark;
+
 #[cfg(feature = "diesel")]
 #[macro_use]
 extern crate diesel;
@@ -75,6 +80,7 @@ use std::env;
 use std::path::PathBuf;
 
 use hyper::server::{Server, Request, Response};
+use hyper::status::StatusCode;
 use reroute::{RouterBuilder, Captures};
 use hyper::header::{AccessControlAllowOrigin, AccessControlAllowHeaders};
 
@@ -105,6 +111,7 @@ trait Container<T> {
 #[derive(Debug)]
 struct User {
     email: String,
+    #[serde(skip_serializing, default)]
     token: String,
     username: String,
     bio: Option<String>,
@@ -143,11 +150,18 @@ struct UpdateArticleDetail {
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
+#[allow(non_snake_case)]
 pub struct Profile {
     username: String,
     bio: Option<String>,
     image: Option<String>,
     following: bool,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    followersCount: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    followingCount: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    articlesCount: Option<i64>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -191,8 +205,11 @@ struct ErrorDetail {
 #[derive(Debug)]
 #[derive(Serialize, Deserialize)]
 struct RegistrationDetails {
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     username: String,
+    #[serde(default)]
     password: String,
 }
 
@@ -202,14 +219,73 @@ pub struct Registration {
 }
 
 #[derive(Serialize, Deserialize)]
+#[derive(Debug)]
 pub struct UserResult {
     user: User,
 }
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct LoginResult {
+    user: User,
+    refreshToken: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+#[allow(non_snake_case)]
+struct RefreshDetails {
+    #[serde(default)]
+    refreshToken: String,
+    #[serde(default)]
+    everywhere: bool,
+}
+
+#[derive(Debug)]
+#[derive(Serialize, Deserialize)]
+struct PasswordResetDetails {
+    #[serde(default)]
+    email: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PasswordReset {
+    user: PasswordResetDetails,
+}
+
+#[derive(Debug)]
+#[derive(Serialize, Deserialize)]
+struct PasswordResetConfirmDetails {
+    #[serde(default)]
+    token: String,
+    #[serde(default)]
+    password: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct PasswordResetConfirm {
+    user: PasswordResetConfirmDetails,
+}
+
+#[derive(Debug)]
+#[derive(Serialize, Deserialize)]
+struct ChangeRoleDetails {
+    #[serde(default)]
+    role: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ChangeRole {
+    user: ChangeRoleDetails,
+}
+
 #[derive(Debug)]
 #[derive(Serialize, Deserialize)]
 struct LoginDetails {
+    #[serde(default)]
     email: String,
+    #[serde(default)]
     password: String,
 }
 
@@ -221,6 +297,16 @@ struct Login {
 #[derive(Debug, Deserialize)]
 struct Config {
     database: Option<DatabaseConfig>,
+    password: Option<PasswordConfig>,
+    session: Option<SessionConfig>,
+    mail: Option<MailConfig>,
+    verification: Option<VerificationConfig>,
+    login: Option<LoginConfig>,
+    moderation: Option<ModerationConfig>,
+    retention: Option<RetentionConfig>,
+    events: Option<EventsConfig>,
+    webhooks: Option<WebhooksConfig>,
+    feeds: Option<FeedsConfig>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -233,6 +319,86 @@ struct DatabaseConfig {
     DATABASE_URL: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct PasswordConfig {
+    algorithm: Option<String>,
+    pbkdf2_iterations: Option<u32>,
+    scrypt_log_n: Option<u8>,
+    scrypt_r: Option<u32>,
+    scrypt_p: Option<u32>,
+    min_length: Option<usize>,
+    require_digit: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SessionConfig {
+    access_token_minutes: Option<u64>,
+    refresh_token_days: Option<i64>,
+    reset_token_minutes: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MailConfig {
+    sender: Option<String>,
+    file: Option<String>,
+    link_base: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerificationConfig {
+    allow_unverified_articles: Option<bool>,
+    allow_unverified_comments: Option<bool>,
+    token_hours: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct LoginConfig {
+    max_failed_attempts: Option<u32>,
+    max_failed_attempts_per_ip: Option<u32>,
+    backoff_base_seconds: Option<u64>,
+    max_lockout_seconds: Option<u64>,
+    forget_after_minutes: Option<u64>,
+    trust_forwarded_for: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ModerationConfig {
+    hide_after_reports: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RetentionConfig {
+    restore_window_hours: Option<i64>,
+    purge_interval_minutes: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventsConfig {
+    heartbeat_seconds: Option<u64>,
+    history_size: Option<usize>,
+    server_threads: Option<usize>,
+    max_streams: Option<usize>,
+    max_streams_per_client: Option<usize>,
+    schedule_poll_seconds: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhooksConfig {
+    max_attempts: Option<i32>,
+    backoff_base_seconds: Option<i64>,
+    timeout_seconds: Option<u64>,
+    poll_seconds: Option<u64>,
+    allowed_hosts: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeedsConfig {
+    title: Option<String>,
+    site_url: Option<String>,
+    api_url: Option<String>,
+    size: Option<usize>,
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 struct UpdateUser {
@@ -249,9 +415,40 @@ struct AddComment {
 #[derive(Debug)]
 #[allow(non_snake_case)]
 struct AddCommentDetail {
+    #[serde(default)]
     body: String,
 }
 
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddReport {
+    report: AddReportDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddReportDetail {
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddWebhook {
+    webhook: AddWebhookDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+struct AddWebhookDetail {
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    events: Vec<String>,
+    #[serde(default)]
+    global: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
 struct CreateArticle {
@@ -268,20 +465,53 @@ struct CreateArticleResult {
 #[derive(Debug)]
 #[allow(non_snake_case)]
 struct CreateArticleDetail {
+    #[serde(default)]
     title: String,
+    #[serde(default)]
     description: String,
+    #[serde(default)]
     body: String,
     tagList: Option<Vec<String>>,
+    #[serde(default)]
+    status: String,
+    publishAt: Option<NaiveDateTime>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+struct PublishArticle {
+    article: PublishArticleDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+#[allow(non_snake_case)]
+struct PublishArticleDetail {
+    publishAt: Option<NaiveDateTime>,
 }
 
 #[derive(Serialize, Deserialize)]
 #[derive(Debug)]
+#[allow(non_snake_case)]
 struct UpdateUserDetail {
     email: Option<String>,
     username: Option<String>,
     password: Option<String>,
-    bio: Option<String>,
-    image: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    bio: Option<Option<String>>,
+    #[serde(default, deserialize_with = "deserialize_nullable")]
+    image: Option<Option<String>>,
+    currentPassword: Option<String>,
+}
+
+/// Tells an explicit `null` (`Some(None)`) apart from a missing field (`None`).
+fn deserialize_nullable<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    use serde::Deserialize;
+
+    Option::deserialize(deserializer).map(Some)
 }
 
 #[derive(Serialize, Deserialize)]
@@ -292,6 +522,12 @@ struct GetTagsResult {
 
 static CONFIG_FILE_NAME: &'static str = r#"conduit.toml"#;
 
+/// `conduit.toml` unless `CONDUIT_CONFIG` names another file, the tests run against
+/// `conduit - test.toml`.
+fn config_file_name() -> String {
+    env::var("CONDUIT_CONFIG").unwrap_or(CONFIG_FILE_NAME.to_string())
+}
+
 #[cfg(feature = "tiberius")]
 lazy_static! {
     pub static ref CONNECTION_STRING : String = match get_database_config().connection_string {
@@ -315,7 +551,7 @@ lazy_static! {
         };  
 }
 
-fn get_database_config() -> DatabaseConfig {
+fn get_config() -> Config {
 
     let env_config = match env::var("DATABASECONFIG") {
         Ok(lang) => lang,
@@ -324,7 +560,7 @@ fn get_database_config() -> DatabaseConfig {
     let mut content = env_config.replace("&&&", "\n");
 
     let mut path = PathBuf::from(env::current_dir().unwrap());
-    path.push(CONFIG_FILE_NAME);
+    path.push(config_file_name());
     let display = path.display();
 
     if path.exists() {
@@ -340,9 +576,11 @@ fn get_database_config() -> DatabaseConfig {
     }
 
     let toml_str: &str = &content;
-    let config: Config = toml::from_str(toml_str).unwrap();
+    toml::from_str(toml_str).unwrap()
+}
 
-    let database_config: DatabaseConfig = match config.database {
+fn get_database_config() -> DatabaseConfig {
+    let database_config: DatabaseConfig = match get_config().database {
         Some(database_config) => database_config,
         None => panic!("database not present in {}", CONFIG_FILE_NAME),
     };
@@ -359,16 +597,47 @@ fn prepare_parameters(mut req: Request) -> (String, i32) {
     let logged_id: i32 = match token {
         Some(token) => {
             let jwt = &token.0.token;
-            login(&jwt).unwrap()
-
+            // expired or revoked tokens are treated like no token at all
+            match login(&jwt) {
+                Some(user_id) if is_suspended(user_id) => SUSPENDED_USER_ID,
+                Some(user_id) => user_id,
+                None => 0,
+            }
         }
         _ => 0,
     };
 
-    println!("body: {}, logged_id: {}", body, logged_id);
+    // bodies carry passwords and tokens, they stay out of the log
+    println!("logged_id: {}", logged_id);
     (body, logged_id)
 }
 
+/// `offset` and `limit` from a query string, 0 and 20 unless given; a page holds 1 to 100 items.
+fn parse_paging(query: &str) -> (i64, i64) {
+    let mut offset: i64 = 0;
+    let mut limit: i64 = 20;
+    for param in query.split('&') {
+        let name_value: Vec<&str> = param.split('=').collect();
+        let value = name_value.get(1).and_then(|v| v.parse::<i64>().ok());
+
+        if name_value[0] == "offset" {
+            offset = value.unwrap_or(offset);
+        } else if name_value[0] == "limit" {
+            limit = value.unwrap_or(limit);
+        }
+    }
+    (offset.max(0), limit.max(1).min(100))
+}
+
+#[cfg(test)]
+#[test]
+fn parse_paging_test() {
+    assert_eq!(parse_paging(""), (0, 20));
+    assert_eq!(parse_paging("offset=40&limit=10&tag=dragons"), (40, 10));
+    assert_eq!(parse_paging("offset=-1&limit=0"), (0, 1));
+    assert_eq!(parse_paging("limit=100000&offset=x"), (0, 100));
+}
+
 use unicase::UniCase;
 use hyper::header::ContentType;
 use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
@@ -381,6 +650,22 @@ pub fn establish_connection() -> PgConnection {
     PgConnection::establish(&database_url).expect(&format!("Error connecting to {}", database_url))
 }
 
+/// Like `establish_connection`, but logs a failure instead of panicking, for the background
+/// threads which have to outlive a database outage.
+#[cfg(feature = "diesel")]
+pub fn try_establish_connection() -> Option<PgConnection> {
+    dotenv().ok();
+
+    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    match PgConnection::establish(&database_url) {
+        Ok(connection) => Some(connection),
+        Err(error) => {
+            println!("Error connecting to {}: {}", database_url, error);
+            None
+        }
+    }
+}
+
 #[cfg(feature = "tiberius")]
 fn process<'a, T>(
     mut res: Response,
@@ -459,6 +744,28 @@ where
     }
 }
 
+/// Sends `result` the way `process` does, but without logging it, for results that carry
+/// tokens.
+fn send_result<T>(mut res: Response, result: T)
+where
+    T: serde::Serialize,
+{
+    res.headers_mut().set(AccessControlAllowOrigin::Any);
+    res.headers_mut().set(AccessControlAllowHeaders(vec![
+        UniCase("content-type".to_owned()),
+        UniCase("authorization".to_owned()),
+    ]));
+    res.headers_mut().set(ContentType(Mime(
+        TopLevel::Application,
+        SubLevel::Json,
+        vec![(Attr::Charset, Value::Utf8)],
+    )));
+
+    let result = serde_json::to_string(&result).unwrap();
+    let result: &[u8] = result.as_bytes();
+    res.send(&result).unwrap();
+}
+
 #[cfg(feature = "tiberius")]
 fn process_container<'a, T, U>(
     mut res: Response,
@@ -542,12 +849,110 @@ fn process_container<'a, T, U, V>(
     res.send(&result).unwrap();
 }
 
+/// What `prepare_parameters` reports for a valid token of a suspended account.
+pub static SUSPENDED_USER_ID: i32 = -1;
+
+/// Answers 401 unless the request carried a valid token, 403 when its account is
+/// suspended, otherwise hands `res` back.
+fn require_login(res: Response, logged_id: i32) -> Option<Response> {
+    if logged_id > 0 {
+        return Some(res);
+    }
+    if logged_id == SUSPENDED_USER_ID {
+        send_errors(res, StatusCode::Forbidden, ValidationErrors::with("user", "is suspended"));
+    } else {
+        send_errors(res, StatusCode::Unauthorized, ValidationErrors::with("token", "is missing or invalid"));
+    }
+    None
+}
+
+fn send_errors(mut res: Response, status: StatusCode, errors: ValidationErrors) {
+    res.headers_mut().set(AccessControlAllowOrigin::Any);
+    res.headers_mut().set(AccessControlAllowHeaders(vec![
+        UniCase("content-type".to_owned()),
+        UniCase("authorization".to_owned()),
+    ]));
+    res.headers_mut().set(ContentType(Mime(
+        TopLevel::Application,
+        SubLevel::Json,
+        vec![(Attr::Charset, Value::Utf8)],
+    )));
+    *res.status_mut() = status;
+
+    let result = serde_json::to_string(&errors).unwrap();
+    let result: &[u8] = result.as_bytes();
+    res.send(&result).unwrap();
+}
+
+mod validation;
+use validation::*;
+
+mod password;
+use password::*;
+
 mod user;
 use user::*;
 
+mod session;
+use session::*;
+
+mod login_throttle;
+use login_throttle::*;
+
+mod mailer;
+use mailer::*;
+
+mod password_reset;
+use password_reset::*;
+
+mod verification;
+use verification::*;
+
+mod permission;
+use permission::*;
+
+mod admin;
+use admin::*;
+
+mod block;
+use block::*;
+
 mod article;
 use article::*;
 
+mod revision;
+use revision::*;
+
+mod trash;
+use trash::*;
+
+mod markdown;
+use markdown::*;
+
+mod article_stats;
+use article_stats::*;
+
+mod bookmark;
+use bookmark::*;
+
+mod follow;
+use follow::*;
+
+mod notification;
+use notification::*;
+
+mod events;
+use events::*;
+
+mod webhook;
+use webhook::*;
+
+mod syndication;
+use syndication::*;
+
+mod report;
+use report::*;
+
 mod comment;
 use comment::*;
 
@@ -680,31 +1085,89 @@ fn main() {
     #[cfg(feature = "tiberius")] builder.post(r"/createdb", create_db_handler);
 
     builder.post(r"/api/users/login", authentication_handler);
+    builder.post(r"/api/users/refresh", refresh_handler);
+    builder.post(r"/api/users/logout", logout_handler);
+    builder.post(r"/api/users/password-reset/confirm", confirm_password_reset_handler);
+    builder.post(r"/api/users/password-reset", password_reset_handler);
+    builder.get(r"/api/users/verify.*", verify_email_handler);
     builder.post(r"/api/users", registration_handler);
     builder.get(r"/api/user", get_current_user_handler);
+    builder.get(r"/api/user/bookmarks.*", list_bookmarks_handler);
     builder.get(r"/test", test_handler);
+    builder.post(r"/api/admin/users/.*/suspend", suspend_user_handler);
+    builder.delete(r"/api/admin/users/.*/suspend", unsuspend_user_handler);
+    builder.post(r"/api/admin/users/.*/password-reset", force_password_reset_handler);
+    builder.put(r"/api/admin/users/.*", change_role_handler);
+    builder.delete(r"/api/admin/users/.*", delete_user_handler);
+    builder.get(r"/api/admin/users.*", list_users_handler);
     builder.put(r"/api/user", update_user_handler);
+    builder.get(r"/api/profiles/.*/followers(\?.*)?", get_followers_handler);
+    builder.get(r"/api/profiles/.*/following(\?.*)?", get_following_handler);
+    builder.get(r"/api/profiles/.*/feed.atom(\?.*)?", author_feed_handler);
     builder.get(r"/api/profiles/.*", get_profile_handler);
     builder.post(r"/api/profiles/.*/follow", follow_handler);
+    builder.post(r"/api/profiles/.*/block", block_handler);
+    builder.delete(r"/api/profiles/.*/block", unblock_handler);
+    builder.post(r"/api/profiles/.*/mute", mute_handler);
+    builder.delete(r"/api/profiles/.*/mute", unmute_handler);
     builder.delete(r"/api/profiles/.*/follow", unfollow_handler);
     builder.post(r"/api/articles", create_article_handler);
 
-    builder.get(r"/api/tags", get_tags_handler);
+    builder.get(r"/api/notifications/preferences", get_notification_preferences_handler);
+    builder.put(r"/api/notifications/preferences", update_notification_preferences_handler);
+    builder.post(r"/api/notifications/read", read_notifications_handler);
+    builder.get(r"/api/notifications(\?.*)?", list_notifications_handler);
+    builder.get(r"/api/events", user_events_handler);
+
+    builder.get(r"/api/webhooks/.*/deliveries(\?.*)?", list_deliveries_handler);
+    builder.delete(r"/api/webhooks/.*", delete_webhook_handler);
+    builder.post(r"/api/webhooks", create_webhook_handler);
+    builder.get(r"/api/webhooks", list_webhooks_handler);
 
+    builder.get(r"/api/tags", get_tags_handler);
+    builder.get(r"/api/tags/.*/feed.atom(\?.*)?", tag_feed_handler);
+
+    builder.get(r"/api/reports", list_reports_handler);
+    builder.post(r"/api/reports/.*/dismiss", dismiss_report_handler);
+    builder.post(r"/api/reports/.*/remove", remove_reported_handler);
+    builder.post(r"/api/articles/.*/comments/.*/report", report_comment_handler);
+    builder.post(r"/api/articles/.*/report", report_article_handler);
+    builder.post(r"/api/articles/.*/publish", publish_article_handler);
+    builder.post(r"/api/articles/.*/unpublish", unpublish_article_handler);
+    builder.post(r"/api/articles/.*/revisions/.*/restore", restore_revision_handler);
+    builder.post(r"/api/articles/.*/comments/.*/restore", restore_comment_handler);
+    builder.post(r"/api/articles/.*/restore", restore_article_handler);
     builder.post(r"/api/articles/.*/comments", add_comment_handler);
     builder.post(r"/api/articles/.*/favorite", favorite_article_handler);
     builder.delete(r"/api/articles/.*/favorite", unfavorite_article_handler);
+    builder.post(r"/api/articles/.*/bookmark", bookmark_handler);
+    builder.delete(r"/api/articles/.*/bookmark", unbookmark_handler);
     builder.put(r"/api/articles/.*", update_article_handler);
     builder.delete(r"/api/articles/.*/comments/.*", delete_comment_handler);
     builder.delete(r"/api/articles/.*", delete_article_handler);
+    builder.get(r"/api/articles.rss(\?.*)?", rss_feed_handler);
+    builder.get(r"/api/articles.atom(\?.*)?", atom_feed_handler);
     builder.get(r"/api/articles/feed", feed_handler);
-    builder.get(r"/api/articles/.*/comments", get_comments_handler);
+    builder.get(r"/api/articles/.*/comments(\?.*)?", get_comments_handler);
+    builder.get(r"/api/articles/.*/events", article_events_handler);
+    builder.get(r"/api/articles/.*/revisions/.*/diff/.*", revision_diff_handler);
+    builder.get(r"/api/articles/.*/revisions/.*", get_revision_handler);
+    builder.get(r"/api/articles/.*/revisions", list_revisions_handler);
     builder.get(r"/api/articles/.*", get_article_handler);
     builder.get(r"/api/articles?.*", list_article_handler);
     builder.options("/api/.*", options_handler);
 
     let router = builder.finalize().unwrap();
 
-    Server::http(listen_on).unwrap().handle(router).unwrap();
+    #[cfg(feature = "diesel")]
+    start_password_reset_thread();
+    #[cfg(feature = "diesel")]
+    start_purge_thread();
+    #[cfg(feature = "diesel")]
+    start_webhook_thread();
+    #[cfg(feature = "diesel")]
+    start_schedule_thread();
+
+    Server::http(listen_on).unwrap().handle_threads(router, *SERVER_THREADS).unwrap();
 
 }
@@ -0,0 +1,206 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+
+use hyper::server::Request;
+use pulldown_cmark::{html, Event, Parser, Tag, OPTION_ENABLE_TABLES};
+
+use super::*;
+
+/// Clients ask for `bodyHtml` with `?render=html` or with `render=html` in the Accept header,
+/// e.g. `Accept: application/json; render=html`.
+pub fn wants_html(req: &Request) -> bool {
+    let in_query = req.uri
+        .to_string()
+        .splitn(2, '?')
+        .nth(1)
+        .map_or(false, |query| query.split('&').any(|param| param == "render=html"));
+    let in_accept = req.headers
+        .get_raw("Accept")
+        .map_or(false, |values| {
+            values
+                .iter()
+                .any(|value| String::from_utf8_lossy(value).replace(' ', "").contains(";render=html"))
+        });
+    in_query || in_accept
+}
+
+/// Schemes links and images may point to, anything without a scheme is a relative link.
+static SAFE_URL_SCHEMES: &'static [&'static str] = &["http", "https", "mailto"];
+
+pub fn is_safe_url(url: &str) -> bool {
+    // browsers skip whitespace and control characters inside a scheme, so "java\tscript:" counts too
+    let url: String = url.chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_lowercase();
+
+    match url.find(|c| c == ':' || c == '/' || c == '?' || c == '#') {
+        Some(position) if url[position..].starts_with(':') => SAFE_URL_SCHEMES.contains(&&url[..position]),
+        _ => true,
+    }
+}
+
+fn sanitize_tag(tag: Tag) -> Tag {
+    match tag {
+        Tag::Link(url, title) => if is_safe_url(&url) {
+            Tag::Link(url, title)
+        } else {
+            Tag::Link("".into(), title)
+        },
+        Tag::Image(url, title) => if is_safe_url(&url) {
+            Tag::Image(url, title)
+        } else {
+            Tag::Image("".into(), title)
+        },
+        tag => tag,
+    }
+}
+
+/// CommonMark to HTML. Only markup CommonMark produces comes out: raw HTML in the text is
+/// escaped and shown as written, links and images keep only the schemes allowed above.
+pub fn render_markdown(text: &str) -> String {
+    let events = Parser::new_ext(text, OPTION_ENABLE_TABLES).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) => Event::Text(raw),
+        Event::Start(tag) => Event::Start(sanitize_tag(tag)),
+        Event::End(tag) => Event::End(sanitize_tag(tag)),
+        event => event,
+    });
+
+    let mut result = String::new();
+    html::push_html(&mut result, events);
+    result
+}
+
+/// Past this many rendered bodies the cache starts over.
+const RENDER_CACHE_SIZE: usize = 10000;
+
+lazy_static! {
+    /// Rendered bodies by kind ("article" or "comment"), id and a hash of the body.
+    static ref RENDER_CACHE : Mutex<HashMap<(&'static str, i32, u64), String>> = Mutex::new(HashMap::new());
+}
+
+/// A changed body gets rendered afresh, whatever else happened to its article meanwhile.
+fn cached_render(kind: &'static str, item_id: i32, body: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    body.hash(&mut hasher);
+    let key = (kind, item_id, hasher.finish());
+
+    if let Some(rendered) = RENDER_CACHE.lock().unwrap().get(&key) {
+        return rendered.to_owned();
+    }
+
+    let rendered = render_markdown(body);
+    let mut cache = RENDER_CACHE.lock().unwrap();
+    if cache.len() >= RENDER_CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, rendered.to_owned());
+    rendered
+}
+
+pub fn article_body_html(article_id: i32, body: &str) -> String {
+    cached_render("article", article_id, body)
+}
+
+pub fn comment_body_html(comment_id: i32, body: &str) -> String {
+    cached_render("comment", comment_id, body)
+}
+
+/// `item` as JSON with a `bodyHtml` next to its other fields.
+pub fn with_body_html<T: serde::Serialize>(item: &T, body_html: String) -> serde_json::Value {
+    let mut value = serde_json::to_value(item).unwrap();
+    if let Some(fields) = value.as_object_mut() {
+        fields.insert("bodyHtml".to_string(), serde_json::Value::String(body_html));
+    }
+    value
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct RenderedArticlesResult {
+    pub articles: Vec<serde_json::Value>,
+}
+
+impl Container<serde_json::Value> for RenderedArticlesResult {
+    fn create_new_with_items(articles: Vec<serde_json::Value>) -> RenderedArticlesResult {
+        RenderedArticlesResult { articles: articles }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct RenderedCommentsResult {
+    pub comments: Vec<serde_json::Value>,
+}
+
+#[cfg(feature = "diesel")]
+pub fn render_articles(articles: Vec<Article>) -> Vec<serde_json::Value> {
+    articles
+        .iter()
+        .map(|article| with_body_html(article, article_body_html(article.id, &article.body)))
+        .collect()
+}
+
+pub fn render_comments(comments: Vec<Comment>) -> Vec<serde_json::Value> {
+    comments
+        .iter()
+        .map(|comment| with_body_html(comment, comment_body_html(comment.id, &comment.body)))
+        .collect()
+}
+
+#[cfg(test)]
+#[test]
+fn render_markdown_test() {
+    assert_eq!(render_markdown("*dragons*"), "<p><em>dragons</em></p>\n");
+    assert!(render_markdown("<script>alert(1)</script>").starts_with("&lt;script&gt;"));
+    assert!(!render_markdown("hi <img src=x onerror=alert(1)>").contains("<img"));
+    assert_eq!(render_markdown("[x](javascript:alert(1))"), "<p><a href=\"\">x</a></p>\n");
+    assert_eq!(render_markdown("[x](JavaScript:alert(1))"), "<p><a href=\"\">x</a></p>\n");
+    assert_eq!(
+        render_markdown("[x](https://example.com)"),
+        "<p><a href=\"https://example.com\">x</a></p>\n"
+    );
+
+    assert!(is_safe_url("/articles/how-to-train-your-dragon"));
+    assert!(is_safe_url("mailto:jake@jake.jake"));
+    assert!(!is_safe_url("data:text/html;base64,PHNjcmlwdD4="));
+    assert!(!is_safe_url(" java\tscript:alert(1)"));
+}
+
+#[cfg(test)]
+#[test]
+fn render_cache_test() {
+    assert_eq!(article_body_html(-1, "*old*"), "<p><em>old</em></p>\n");
+    assert_eq!(article_body_html(-1, "*new*"), "<p><em>new</em></p>\n");
+    assert_eq!(comment_body_html(-1, "*old*"), "<p><em>old</em></p>\n");
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn body_html_test() {
+    let client = Client::new();
+    let (_, slug, _) = login_create_article(false);
+
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles/{}?render=html", slug))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: ArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.article.bodyHtml, Some("<p>You have to believe</p>\n".to_string()));
+
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles/{}", slug))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: ArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.article.bodyHtml, None);
+}
@@ -7,16 +7,26 @@ use chrono::prelude::*;
 #[derive(Identifiable, Queryable, Associations)]
 #[derive(Serialize, Deserialize)]
 #[has_many(favoritedarticles)]
+#[has_many(sessions)]
 #[derive(Debug)]
+#[allow(non_snake_case)]
 #[cfg(feature = "diesel")]
 pub struct User {
     pub id: i32,
     pub email: String,
+    // the password hash, never part of a response
+    #[serde(skip_serializing, default)]
     pub token: String,
     pub username: String,
     pub bio: Option<String>,
     pub image: Option<String>,
     //pub following: Option<bool>
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub tokenversion: i32,
+    pub verified: bool,
+    pub role: String,
+    pub suspendedAt: Option<NaiveDateTime>,
 }
 
 #[derive(Identifiable, Queryable, Associations)]
@@ -52,10 +62,19 @@ pub struct AdvancedArticle {
     pub body: String,
     pub createdAt: NaiveDateTime,
     pub updatedAt: Option<NaiveDateTime>,
+    /// The author's user id, see `Comment::author` for the profile.
     pub author: i32,
     pub favorited: bool,
     pub favoritesCount: i64,
     pub tagList: Vec<String>,
+    pub status: String,
+    pub publishAt: Option<NaiveDateTime>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub bodyHtml: Option<String>,
+    pub wordCount: i32,
+    pub readingTimeMinutes: i32,
+    pub excerpt: String,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -73,6 +92,83 @@ pub struct UpdatedArticle<'a> {
     pub author: i32,
     pub createdat: NaiveDateTime,
     pub updatedat: Option<NaiveDateTime>,
+    pub wordcount: i32,
+    pub readingtimeminutes: i32,
+    pub excerpt: &'a str,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[table_name = "sessions"]
+#[allow(non_snake_case)]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct Session {
+    pub id: i32,
+    pub userid: i32,
+    pub tokenhash: String,
+    pub createdAt: NaiveDateTime,
+    pub expiresAt: NaiveDateTime,
+    pub revokedAt: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "sessions"]
+#[derive(Debug)]
+pub struct NewSession<'a> {
+    pub userid: i32,
+    pub tokenhash: &'a str,
+    pub createdat: NaiveDateTime,
+    pub expiresat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[table_name = "passwordresets"]
+#[allow(non_snake_case)]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct PasswordResetToken {
+    pub id: i32,
+    pub userid: i32,
+    pub tokenhash: String,
+    pub createdAt: NaiveDateTime,
+    pub expiresAt: NaiveDateTime,
+    pub usedAt: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "passwordresets"]
+#[derive(Debug)]
+pub struct NewPasswordResetToken<'a> {
+    pub userid: i32,
+    pub tokenhash: &'a str,
+    pub createdat: NaiveDateTime,
+    pub expiresat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[table_name = "emailverifications"]
+#[allow(non_snake_case)]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct EmailVerification {
+    pub id: i32,
+    pub userid: i32,
+    pub tokenhash: String,
+    pub email: String,
+    pub createdAt: NaiveDateTime,
+    pub expiresAt: NaiveDateTime,
+    pub usedAt: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "emailverifications"]
+#[derive(Debug)]
+pub struct NewEmailVerification<'a> {
+    pub userid: i32,
+    pub tokenhash: &'a str,
+    pub email: &'a str,
+    pub createdat: NaiveDateTime,
+    pub expiresat: NaiveDateTime,
 }
 
 #[derive(Insertable)]
@@ -84,7 +180,7 @@ pub struct NewUser<'a> {
     pub username: &'a str,
 }
 
-#[derive(Insertable)]
+/// Omitted fields (`None`) are left untouched, `Some(None)` clears `image` and `bio`.
 #[table_name = "users"]
 #[derive(AsChangeset)]
 #[derive(Identifiable)]
@@ -92,11 +188,13 @@ pub struct NewUser<'a> {
 #[allow(non_snake_case)]
 pub struct UpdatedUser<'a> {
     pub id: i32,
-    pub email: &'a str,
-    pub token: &'a str,
-    pub username: &'a str,
-    pub image: &'a str,
-    pub bio: &'a str,
+    pub email: Option<&'a str>,
+    pub token: Option<&'a str>,
+    pub username: Option<&'a str>,
+    pub image: Option<Option<&'a str>>,
+    pub bio: Option<Option<&'a str>>,
+    pub tokenversion: Option<i32>,
+    pub verified: Option<bool>,
 }
 
 #[derive(Identifiable, Queryable, Associations)]
@@ -110,11 +208,18 @@ pub struct Comment {
     pub createdAt: NaiveDateTime,
     pub updatedAt: Option<NaiveDateTime>,
     pub body: String,
-    //author: Profile,
+    /// The author's user id. The Diesel build doesn't embed author profiles yet, so there's no
+    /// `following` here; GET /api/profiles/:username has it, relative to the viewer.
     pub author: i32,
     #[serde(skip_serializing)]
     #[serde(skip_deserializing)]
     pub articleid : i32,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub hidden: bool,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub deletedAt: Option<NaiveDateTime>,
 }
 
 #[derive(Insertable)]
@@ -141,6 +246,11 @@ pub struct NewArticle<'a> {
     pub createdat: NaiveDateTime,
     pub updatedat: Option<NaiveDateTime>,
     //pub tagList: &'a Vec<str>,
+    pub status: &'a str,
+    pub publishat: Option<NaiveDateTime>,
+    pub wordcount: i32,
+    pub readingtimeminutes: i32,
+    pub excerpt: &'a str,
 }
 
 #[derive(Queryable)]
@@ -178,6 +288,20 @@ pub struct Article {
     pub createdAt: NaiveDateTime,
     pub updatedAt: Option<NaiveDateTime>,
     pub author: i32,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub hidden: bool,
+    pub status: String,
+    pub publishAt: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub takenDownAt: Option<NaiveDateTime>,
+    #[serde(skip_serializing)]
+    #[serde(skip_deserializing)]
+    pub deletedAt: Option<NaiveDateTime>,
+    pub wordCount: i32,
+    pub readingTimeMinutes: i32,
+    pub excerpt: String,
 }
 
 #[derive(Identifiable, Queryable, Associations)]
@@ -228,3 +352,212 @@ pub struct Tag {
     pub id: i32,
     pub tag: String,
 }
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[table_name = "reports"]
+#[allow(non_snake_case)]
+#[belongs_to(Article, foreign_key = "articleid")]
+pub struct Report {
+    pub id: i32,
+    pub articleid: i32,
+    pub commentid: Option<i32>,
+    pub reporter: i32,
+    pub reason: String,
+    pub createdAt: NaiveDateTime,
+    pub resolvedAt: Option<NaiveDateTime>,
+    pub resolution: Option<String>,
+}
+
+#[derive(Insertable)]
+#[table_name = "reports"]
+#[derive(Debug)]
+pub struct NewReport<'a> {
+    pub articleid: i32,
+    pub commentid: Option<i32>,
+    pub reporter: i32,
+    pub reason: &'a str,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable)]
+#[derive(Debug)]
+#[table_name = "userblocks"]
+#[allow(non_snake_case)]
+pub struct UserBlock {
+    pub id: i32,
+    pub blockerid: i32,
+    pub blockedid: i32,
+    pub kind: String,
+    pub createdAt: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "userblocks"]
+#[derive(Debug)]
+pub struct NewUserBlock<'a> {
+    pub blockerid: i32,
+    pub blockedid: i32,
+    pub kind: &'a str,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[table_name = "articlerevisions"]
+#[allow(non_snake_case)]
+#[belongs_to(Article, foreign_key = "articleid")]
+pub struct ArticleRevision {
+    pub id: i32,
+    pub articleid: i32,
+    pub number: i32,
+    pub title: String,
+    pub description: String,
+    pub body: String,
+    pub editorid: i32,
+    pub createdAt: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "articlerevisions"]
+#[derive(Debug)]
+pub struct NewArticleRevision<'a> {
+    pub articleid: i32,
+    pub number: i32,
+    pub title: &'a str,
+    pub description: &'a str,
+    pub body: &'a str,
+    pub editorid: i32,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[table_name = "bookmarks"]
+#[allow(non_snake_case)]
+#[belongs_to(Article, foreign_key = "articleid")]
+pub struct Bookmark {
+    pub id: i32,
+    pub userid: i32,
+    pub articleid: i32,
+    pub createdAt: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "bookmarks"]
+#[derive(Debug)]
+pub struct NewBookmark {
+    pub userid: i32,
+    pub articleid: i32,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[table_name = "notifications"]
+#[allow(non_snake_case)]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct Notification {
+    pub id: i32,
+    pub userid: i32,
+    pub actorid: i32,
+    pub kind: String,
+    pub articleid: Option<i32>,
+    pub commentid: Option<i32>,
+    pub createdAt: NaiveDateTime,
+    pub readAt: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "notifications"]
+#[derive(Debug)]
+pub struct NewNotification<'a> {
+    pub userid: i32,
+    pub actorid: i32,
+    pub kind: &'a str,
+    pub articleid: Option<i32>,
+    pub commentid: Option<i32>,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[table_name = "notificationpreferences"]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct NotificationPreference {
+    pub id: i32,
+    pub userid: i32,
+    pub kind: String,
+    pub enabled: bool,
+}
+
+#[derive(Insertable)]
+#[table_name = "notificationpreferences"]
+#[derive(Debug)]
+pub struct NewNotificationPreference<'a> {
+    pub userid: i32,
+    pub kind: &'a str,
+    pub enabled: bool,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Debug)]
+#[derive(Clone)]
+#[table_name = "webhooks"]
+#[allow(non_snake_case)]
+#[belongs_to(User, foreign_key = "userid")]
+pub struct Webhook {
+    pub id: i32,
+    pub userid: i32,
+    pub url: String,
+    pub secret: String,
+    pub events: String,
+    pub isGlobal: bool,
+    pub createdAt: NaiveDateTime,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhooks"]
+#[derive(Debug)]
+pub struct NewWebhook<'a> {
+    pub userid: i32,
+    pub url: &'a str,
+    pub secret: &'a str,
+    pub events: &'a str,
+    pub isglobal: bool,
+    pub createdat: NaiveDateTime,
+}
+
+#[derive(Identifiable, Queryable, Associations)]
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[table_name = "webhookdeliveries"]
+#[allow(non_snake_case)]
+#[belongs_to(Webhook, foreign_key = "webhookid")]
+pub struct WebhookDelivery {
+    pub id: i32,
+    pub webhookid: i32,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i32,
+    pub responseStatus: Option<i32>,
+    pub lastError: Option<String>,
+    pub createdAt: NaiveDateTime,
+    pub nextAttemptAt: Option<NaiveDateTime>,
+    pub deliveredAt: Option<NaiveDateTime>,
+}
+
+#[derive(Insertable)]
+#[table_name = "webhookdeliveries"]
+#[derive(Debug)]
+pub struct NewWebhookDelivery<'a> {
+    pub webhookid: i32,
+    pub event: &'a str,
+    pub payload: &'a str,
+    pub createdat: NaiveDateTime,
+    pub nextattemptat: Option<NaiveDateTime>,
+}
@@ -0,0 +1,484 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// What happened to a user, or to something they wrote.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationKind {
+    Follow,
+    Favorite,
+    Comment,
+}
+
+impl NotificationKind {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            NotificationKind::Follow => "follow",
+            NotificationKind::Favorite => "favorite",
+            NotificationKind::Comment => "comment",
+        }
+    }
+}
+
+/// A notification as its recipient sees it, with the actor and article by name.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct NotificationEntry {
+    pub id: i32,
+    pub kind: String,
+    pub actor: String,
+    pub article: Option<String>,
+    pub commentId: Option<i32>,
+    pub createdAt: NaiveDateTime,
+    pub read: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct NotificationsResult {
+    pub notifications: Vec<NotificationEntry>,
+    pub unreadCount: i64,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct UnreadCountResult {
+    pub unreadCount: i64,
+}
+
+/// Marks the listed notifications read, or all of them when the list is left out.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+pub struct ReadNotifications {
+    pub notifications: Option<Vec<i32>>,
+}
+
+/// Which kinds of notifications a user gets, all of them unless turned off.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq)]
+pub struct NotificationPreferences {
+    pub follow: bool,
+    pub favorite: bool,
+    pub comment: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct NotificationPreferencesResult {
+    pub preferences: NotificationPreferences,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+pub struct UpdateNotificationPreferences {
+    pub preferences: UpdateNotificationPreferencesDetail,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, Default)]
+pub struct UpdateNotificationPreferencesDetail {
+    pub follow: Option<bool>,
+    pub favorite: Option<bool>,
+    pub comment: Option<bool>,
+}
+
+#[cfg(feature = "diesel")]
+fn is_enabled(user_id: i32, notification_kind: NotificationKind) -> bool {
+    use schema::notificationpreferences::dsl::*;
+
+    let connection = establish_connection();
+    notificationpreferences
+        .filter(userid.eq(user_id))
+        .filter(kind.eq(notification_kind.name()))
+        .select(enabled)
+        .first::<bool>(&connection)
+        .unwrap_or(true)
+}
+
+/// Tells `user_id` that `actor_id` did something. Nobody hears about their own doings, nor
+/// about users they blocked or muted, nor about kinds they turned off.
+#[cfg(feature = "diesel")]
+pub fn notify(user_id: i32, actor_id: i32, kind: NotificationKind, article_id: Option<i32>, comment_id: Option<i32>) {
+    use schema::notifications;
+
+    if user_id == actor_id || get_hidden_authors(user_id).contains(&actor_id) || !is_enabled(user_id, kind) {
+        return;
+    }
+
+    let new_notification = NewNotification {
+        userid: user_id,
+        actorid: actor_id,
+        kind: kind.name(),
+        articleid: article_id,
+        commentid: comment_id,
+        createdat: Utc::now().naive_utc(),
+    };
+
+    let connection = establish_connection();
+    let notification: Notification = diesel::insert(&new_notification)
+        .into(notifications::table)
+        .get_result(&connection)
+        .expect("Error saving notification");
+
+    for entry in to_entries(&connection, vec![notification]) {
+        publish_event(Topic::User(user_id), actor_id, "notification", &entry);
+    }
+}
+
+/// Notifications go along when the article or comment they point at is deleted for good.
+#[cfg(feature = "diesel")]
+pub fn delete_notifications_for(connection: &PgConnection, article_id: i32, comment_id: Option<i32>) -> QueryResult<usize> {
+    use schema::notifications::dsl::*;
+
+    match comment_id {
+        Some(comment_id) => diesel::delete(notifications.filter(commentid.eq(comment_id))).execute(connection),
+        None => diesel::delete(notifications.filter(articleid.eq(article_id))).execute(connection),
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn get_unread_count(connection: &PgConnection, user_id: i32) -> i64 {
+    use schema::notifications::dsl::*;
+
+    notifications
+        .filter(userid.eq(user_id))
+        .filter(readat.is_null())
+        .count()
+        .get_result(connection)
+        .expect("Error counting notifications")
+}
+
+/// Notifications with their actors and articles by name. Notifications only join their
+/// recipient, so actors and articles are looked up for all of them at once instead.
+#[cfg(feature = "diesel")]
+fn to_entries(connection: &PgConnection, loaded: Vec<Notification>) -> Vec<NotificationEntry> {
+    use diesel::expression::dsl::any;
+    use schema::{articles, users};
+
+    let actor_ids: Vec<i32> = loaded.iter().map(|notification| notification.actorid).collect();
+    let article_ids: Vec<i32> = loaded.iter().filter_map(|notification| notification.articleid).collect();
+
+    let actors: Vec<(i32, String)> = users::table
+        .filter(users::id.eq(any(actor_ids)))
+        .select((users::id, users::username))
+        .load(connection)
+        .expect("Error loading notification actors");
+    let slugs: Vec<(i32, String)> = articles::table
+        .filter(articles::id.eq(any(article_ids)))
+        .select((articles::id, articles::slug))
+        .load(connection)
+        .expect("Error loading notification articles");
+
+    let name_of = |names: &Vec<(i32, String)>, wanted: i32| {
+        names.iter().find(|&&(name_id, _)| name_id == wanted).map(|&(_, ref name)| name.to_owned())
+    };
+
+    loaded
+        .into_iter()
+        .map(|notification| NotificationEntry {
+            id: notification.id,
+            actor: name_of(&actors, notification.actorid).unwrap_or_default(),
+            article: notification.articleid.and_then(|article_id| name_of(&slugs, article_id)),
+            kind: notification.kind,
+            commentId: notification.commentid,
+            createdAt: notification.createdAt,
+            read: notification.readAt.is_some(),
+        })
+        .collect()
+}
+
+/// The user's notifications, newest first.
+#[cfg(feature = "diesel")]
+fn get_notifications(params: (i32, i64, i64)) -> Option<NotificationsResult> {
+    use schema::notifications::dsl::*;
+
+    let (user_id, offset, limit) = params;
+    let connection = establish_connection();
+
+    let loaded: Vec<Notification> = notifications
+        .filter(userid.eq(user_id))
+        .order(createdat.desc())
+        .offset(offset)
+        .limit(limit)
+        .load(&connection)
+        .expect("Error loading notifications");
+
+    Some(NotificationsResult {
+        notifications: to_entries(&connection, loaded),
+        unreadCount: get_unread_count(&connection, user_id),
+    })
+}
+
+pub fn list_notifications_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let (offset, limit) = parse_paging(caps[0].splitn(2, '?').nth(1).unwrap_or(""));
+
+    #[cfg(feature = "diesel")]
+    process(res, get_notifications, (logged_id, offset, limit));
+}
+
+#[cfg(feature = "diesel")]
+fn mark_read(params: (i32, Option<Vec<i32>>)) -> Option<UnreadCountResult> {
+    use diesel::expression::dsl::any;
+    use schema::notifications::dsl::*;
+
+    let (user_id, notification_ids) = params;
+    let connection = establish_connection();
+    let now = Some(Utc::now().naive_utc());
+
+    let unread = notifications.filter(userid.eq(user_id)).filter(readat.is_null());
+    match notification_ids {
+        Some(notification_ids) => diesel::update(unread.filter(id.eq(any(&notification_ids))))
+            .set(readat.eq(now))
+            .execute(&connection),
+        None => diesel::update(unread).set(readat.eq(now)).execute(&connection),
+    }.expect("Error marking notifications read");
+
+    Some(UnreadCountResult { unreadCount: get_unread_count(&connection, user_id) })
+}
+
+pub fn read_notifications_handler(req: Request, res: Response, _: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let read: ReadNotifications = if body.trim().is_empty() {
+        ReadNotifications::default()
+    } else {
+        match serde_json::from_str(&body) {
+            Ok(read) => read,
+            Err(_) => {
+                let errors = ValidationErrors::with("notifications", "must be a list of ids");
+                return send_errors(res, StatusCode::UnprocessableEntity, errors);
+            }
+        }
+    };
+
+    #[cfg(feature = "diesel")]
+    process(res, mark_read, (logged_id, read.notifications));
+}
+
+#[cfg(feature = "diesel")]
+fn get_preferences(user_id: i32) -> Option<NotificationPreferencesResult> {
+    use schema::notificationpreferences::dsl::*;
+
+    let connection = establish_connection();
+    let stored: Vec<(String, bool)> = notificationpreferences
+        .filter(userid.eq(user_id))
+        .select((kind, enabled))
+        .load(&connection)
+        .expect("Error loading notification preferences");
+    let is_on = |wanted: NotificationKind| {
+        stored
+            .iter()
+            .find(|&&(ref stored_kind, _)| stored_kind == wanted.name())
+            .map_or(true, |&(_, stored_enabled)| stored_enabled)
+    };
+
+    Some(NotificationPreferencesResult {
+        preferences: NotificationPreferences {
+            follow: is_on(NotificationKind::Follow),
+            favorite: is_on(NotificationKind::Favorite),
+            comment: is_on(NotificationKind::Comment),
+        },
+    })
+}
+
+/// Saves one preference. The insert leaves an existing row alone instead of failing on it,
+/// so two requests racing to create the row both end up updating it.
+#[cfg(feature = "diesel")]
+fn set_preference(connection: &PgConnection, user_id: i32, notification_kind: NotificationKind, value: bool) {
+    use diesel::pg::upsert::*;
+    use schema::notificationpreferences::dsl::*;
+
+    let preference = NewNotificationPreference {
+        userid: user_id,
+        kind: notification_kind.name(),
+        enabled: value,
+    };
+    let inserted = diesel::insert(&preference.on_conflict_do_nothing())
+        .into(notificationpreferences)
+        .execute(connection)
+        .expect("Error saving notification preference");
+
+    if inserted == 0 {
+        diesel::update(
+            notificationpreferences
+                .filter(userid.eq(user_id))
+                .filter(kind.eq(notification_kind.name())),
+        ).set(enabled.eq(value))
+            .execute(connection)
+            .expect("Error saving notification preference");
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn update_preferences(params: (i32, UpdateNotificationPreferencesDetail)) -> Option<NotificationPreferencesResult> {
+    let (user_id, update) = params;
+    let connection = establish_connection();
+
+    let changes = [
+        (NotificationKind::Follow, update.follow),
+        (NotificationKind::Favorite, update.favorite),
+        (NotificationKind::Comment, update.comment),
+    ];
+    for &(kind, value) in changes.iter() {
+        if let Some(value) = value {
+            set_preference(&connection, user_id, kind, value);
+        }
+    }
+
+    get_preferences(user_id)
+}
+
+pub fn get_notification_preferences_handler(req: Request, res: Response, _: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    #[cfg(feature = "diesel")]
+    process(res, get_preferences, logged_id);
+}
+
+pub fn update_notification_preferences_handler(req: Request, res: Response, _: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let update: UpdateNotificationPreferences = match serde_json::from_str(&body) {
+        Ok(update) => update,
+        Err(_) => {
+            let errors = ValidationErrors::with("preferences", "can't be read");
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+    };
+
+    #[cfg(feature = "diesel")]
+    process(res, update_preferences, (logged_id, update.preferences));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+fn get_notifications_for(jwt: &str) -> NotificationsResult {
+    let client = Client::new();
+    let mut res = client
+        .get("http://localhost:6767/api/notifications")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    serde_json::from_str(&buffer).unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn notifications_test() {
+    let client = Client::new();
+
+    let (author_jwt, slug, author_name) = login_create_article(false);
+    let (reader_name, reader_email) = register_jacob();
+    let reader_jwt = login_jacob(reader_email, user::JACOB_PASSWORD.to_string());
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/profiles/{}/follow", author_name))
+        .header(Authorization(Bearer { token: reader_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // favoriting twice notifies once
+    for _ in 0..2 {
+        let res = client
+            .post(&format!("http://localhost:6767/api/articles/{}/favorite", slug))
+            .header(Authorization(Bearer { token: reader_jwt.to_owned() }))
+            .send()
+            .unwrap();
+        assert_eq!(res.status, hyper::Ok);
+    }
+
+    let result = get_notifications_for(&author_jwt);
+    assert_eq!(result.unreadCount, 2);
+    assert_eq!(result.notifications[0].kind, "favorite");
+    assert_eq!(result.notifications[0].actor, reader_name);
+    assert_eq!(result.notifications[0].article, Some(slug.to_owned()));
+    assert_eq!(result.notifications[1].kind, "follow");
+
+    let body = format!(r#"{{"notifications": [{}]}}"#, result.notifications[0].id);
+    let mut res = client
+        .post("http://localhost:6767/api/notifications/read")
+        .header(Authorization(Bearer { token: author_jwt.to_owned() }))
+        .body(body.as_str())
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let unread: UnreadCountResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(unread.unreadCount, 1);
+
+    let res = client
+        .post("http://localhost:6767/api/notifications/read")
+        .header(Authorization(Bearer { token: author_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    assert_eq!(get_notifications_for(&author_jwt).unreadCount, 0);
+}
+
+#[cfg(test)]
+#[test]
+fn notification_preferences_test() {
+    let client = Client::new();
+
+    let (author_jwt, slug, _) = login_create_article(false);
+    let (_, reader_email) = register_jacob();
+    let reader_jwt = login_jacob(reader_email, user::JACOB_PASSWORD.to_string());
+
+    let mut res = client
+        .put("http://localhost:6767/api/notifications/preferences")
+        .header(Authorization(Bearer { token: author_jwt.to_owned() }))
+        .body(r#"{"preferences": {"comment": false}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: NotificationPreferencesResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(
+        result.preferences,
+        NotificationPreferences {
+            follow: true,
+            favorite: true,
+            comment: false,
+        }
+    );
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments", slug))
+        .header(Authorization(Bearer { token: reader_jwt }))
+        .body(r#"{"comment": {"body": "Thank you so much!"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result = get_notifications_for(&author_jwt);
+    assert_eq!(result.notifications.len(), 0);
+}
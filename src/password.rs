@@ -0,0 +1,153 @@
+use crypto::pbkdf2::{pbkdf2_check, pbkdf2_simple};
+use crypto::scrypt::{scrypt_check, scrypt_simple, ScryptParams};
+
+use rustc_serialize::base64::FromBase64;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Algorithm {
+    Pbkdf2(u32),
+    Scrypt(u8, u32, u32),
+}
+
+#[derive(Debug)]
+pub struct PasswordPolicy {
+    pub algorithm: Algorithm,
+    pub min_length: usize,
+    pub require_digit: bool,
+}
+
+pub static PASSWORD_MAX_LENGTH: usize = 128;
+
+static COMMON_PASSWORDS: &'static [&'static str] = &[
+    "password",
+    "password1",
+    "12345678",
+    "123456789",
+    "1234567890",
+    "qwertyui",
+    "qwerty123",
+    "iloveyou",
+    "sunshine",
+    "football",
+    "baseball",
+    "letmein1",
+    "11111111",
+    "00000000",
+    "abcd1234",
+];
+
+fn get_password_policy() -> PasswordPolicy {
+    let config = get_config().password;
+
+    let algorithm = match config {
+        Some(ref config) => {
+            match config.algorithm.as_ref().map(|x| &**x).unwrap_or("pbkdf2") {
+                "pbkdf2" => Algorithm::Pbkdf2(config.pbkdf2_iterations.unwrap_or(100000)),
+                "scrypt" => Algorithm::Scrypt(
+                    config.scrypt_log_n.unwrap_or(15),
+                    config.scrypt_r.unwrap_or(8),
+                    config.scrypt_p.unwrap_or(1),
+                ),
+                other => panic!("unknown algorithm {} in [password] section in {}", other, CONFIG_FILE_NAME),
+            }
+        }
+        None => Algorithm::Pbkdf2(100000),
+    };
+
+    PasswordPolicy {
+        algorithm: algorithm,
+        min_length: config.as_ref().and_then(|c| c.min_length).unwrap_or(8),
+        require_digit: config.as_ref().and_then(|c| c.require_digit).unwrap_or(false),
+    }
+}
+
+lazy_static! {
+    pub static ref PASSWORD_POLICY : PasswordPolicy = get_password_policy();
+    // checked against when there is no user, so a login costs the same either way
+    static ref DUMMY_HASH : String = hash_password(&new_random_token());
+}
+
+pub fn hash_password(password: &str) -> String {
+    match PASSWORD_POLICY.algorithm {
+        Algorithm::Pbkdf2(iterations) => pbkdf2_simple(password, iterations).unwrap(),
+        Algorithm::Scrypt(log_n, r, p) => scrypt_simple(password, &ScryptParams::new(log_n, r, p)).unwrap(),
+    }
+}
+
+pub fn verify_password(password: &str, stored_hash: &str) -> bool {
+    let result = if stored_hash.starts_with("$rscrypt$") {
+        scrypt_check(password, stored_hash)
+    } else {
+        pbkdf2_check(password, stored_hash)
+    };
+    result.unwrap_or(false)
+}
+
+/// Like `verify_password`, but does the same work and fails when there is no stored hash.
+pub fn verify_password_or_dummy(password: &str, stored_hash: Option<&str>) -> bool {
+    match stored_hash {
+        Some(stored_hash) => verify_password(password, stored_hash),
+        None => {
+            verify_password(password, &DUMMY_HASH);
+            false
+        }
+    }
+}
+
+/// True when `stored_hash` was made with another algorithm or a lower cost than the policy asks for.
+pub fn needs_rehash(stored_hash: &str) -> bool {
+    // both formats are $<algorithm>$0$<base64 parameters>$<salt>$<hash>$
+    let parts: Vec<&str> = stored_hash.split('$').collect();
+    if parts.len() < 4 {
+        return true;
+    }
+    let params = match parts[3].from_base64() {
+        Ok(params) => params,
+        Err(_) => return true,
+    };
+
+    match (PASSWORD_POLICY.algorithm, parts[1]) {
+        (Algorithm::Pbkdf2(iterations), "rpbkdf2") => {
+            if params.len() != 4 {
+                return true;
+            }
+            let stored_iterations = (params[0] as u32) << 24 | (params[1] as u32) << 16 |
+                (params[2] as u32) << 8 | params[3] as u32;
+            stored_iterations < iterations
+        }
+        (Algorithm::Scrypt(log_n, _, _), "rscrypt") => params.is_empty() || params[0] < log_n,
+        _ => true,
+    }
+}
+
+/// Strength rules on top of the length check, see `Rule::Password`.
+pub fn check_password_strength(password: &str) -> Option<&'static str> {
+    let lowercase = password.to_lowercase();
+    if COMMON_PASSWORDS.contains(&lowercase.as_str()) {
+        return Some("is too common");
+    }
+    if PASSWORD_POLICY.require_digit &&
+        !(password.chars().any(|c| c.is_digit(10)) && password.chars().any(|c| c.is_alphabetic()))
+    {
+        return Some("must contain both letters and numbers");
+    }
+    None
+}
+
+#[cfg(test)]
+#[test]
+fn rehash_test() {
+    let weak = pbkdf2_simple("jakejake", 1000).unwrap();
+    assert!(verify_password("jakejake", &weak));
+    assert!(needs_rehash(&weak));
+
+    let current = hash_password("jakejake");
+    assert!(verify_password("jakejake", &current));
+    assert!(!verify_password("jakejake!", &current));
+    assert!(!needs_rehash(&current));
+
+    assert!(!verify_password_or_dummy("jakejake", None));
+    assert!(verify_password_or_dummy("jakejake", Some(&current)));
+}
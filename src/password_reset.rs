@@ -0,0 +1,233 @@
+use std::sync::Mutex;
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// Reset requests waiting for the mail thread, more are dropped until it catches up.
+static RESET_QUEUE_SIZE: usize = 100;
+
+lazy_static! {
+    pub static ref RESET_TOKEN_MINUTES : i64 = get_config()
+        .session
+        .and_then(|c| c.reset_token_minutes)
+        .unwrap_or(60);
+    /// Feeds the mail thread the addresses to send reset links to.
+    static ref RESET_REQUESTS : Mutex<Option<SyncSender<String>>> = Mutex::new(None);
+}
+
+#[cfg(feature = "diesel")]
+pub fn create_password_reset(connection: &PgConnection, user: User) -> QueryResult<()> {
+    use schema::passwordresets;
+
+    let reset_token = new_random_token();
+    let now = Utc::now().naive_utc();
+    let new_reset = NewPasswordResetToken {
+        userid: user.id,
+        tokenhash: &hash_token(&reset_token),
+        createdat: now,
+        expiresat: now + chrono::Duration::minutes(*RESET_TOKEN_MINUTES),
+    };
+
+    let _reset: PasswordResetToken = diesel::insert(&new_reset)
+        .into(passwordresets::table)
+        .get_result(connection)?;
+
+    send_mail(
+        &user.email,
+        "Reset your Conduit password",
+        &format!(
+            "Hi {},\n\nsomebody asked to reset your password. Use this link within {} minutes to choose a new one:\n{}/reset-password?token={}\n\nIf it wasn't you, just ignore this mail.",
+            user.username,
+            *RESET_TOKEN_MINUTES,
+            MAIL.link_base,
+            reset_token
+        ),
+    );
+    Ok(())
+}
+
+/// Looks up and mails one reset request after another, see `request_password_reset`.
+#[cfg(feature = "diesel")]
+pub fn start_password_reset_thread() {
+    use schema::users::dsl::*;
+
+    let (sender, requests) = sync_channel::<String>(RESET_QUEUE_SIZE);
+    *RESET_REQUESTS.lock().unwrap() = Some(sender);
+
+    ::std::thread::spawn(move || {
+        for user_email in requests {
+            // an unreachable database costs this request, not the thread
+            let connection = match try_establish_connection() {
+                Some(connection) => connection,
+                None => continue,
+            };
+            let result = users
+                .filter(email.eq(user_email))
+                .first::<User>(&connection)
+                .optional()
+                .and_then(|user| match user {
+                    Some(user) => create_password_reset(&connection, user),
+                    None => Ok(()),
+                });
+            if let Err(error) = result {
+                println!("Error creating password reset: {}", error);
+            }
+        }
+    });
+}
+
+#[cfg(feature = "diesel")]
+fn request_password_reset(user_email: String) -> Option<bool> {
+    // unknown emails get the same empty answer, as quickly as known ones, so nobody learns who is
+    // registered: the lookup and the mail happen on the mail thread after the answer is sent
+    if let Some(ref sender) = *RESET_REQUESTS.lock().unwrap() {
+        if let Err(TrySendError::Full(_)) = sender.try_send(user_email) {
+            println!("Password reset queue is full, dropping a request");
+        }
+    }
+    None
+}
+
+pub fn password_reset_handler(req: Request, res: Response, _: Captures) {
+    let (body, _) = prepare_parameters(req);
+
+    let reset: PasswordReset = match serde_json::from_str(&body) {
+        Ok(reset) => reset,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+    };
+
+    let errors = reset.user.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+
+    #[cfg(feature = "diesel")]
+    process(res, request_password_reset, reset.user.email);
+}
+
+/// Marks an unused, unexpired reset token used and hands it back, in one statement so
+/// concurrent requests can't both use it.
+#[cfg(feature = "diesel")]
+fn use_password_reset(reset_token: &str) -> Option<PasswordResetToken> {
+    use schema::passwordresets::dsl::*;
+
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+
+    diesel::update(
+        passwordresets
+            .filter(tokenhash.eq(hash_token(reset_token)))
+            .filter(usedat.is_null())
+            .filter(expiresat.gt(now)),
+    ).set(usedat.eq(Some(now)))
+        .get_result(&connection)
+        .ok()
+}
+
+#[cfg(feature = "diesel")]
+fn set_password(params: (i32, String)) -> Option<bool> {
+    use schema::users::dsl::*;
+
+    let (user_id, password_hash) = params;
+    let connection = establish_connection();
+
+    diesel::update(users.filter(id.eq(user_id)))
+        .set(token.eq(password_hash))
+        .execute(&connection)
+        .expect("Error resetting password");
+
+    revoke_all_sessions(user_id);
+    None
+}
+
+pub fn confirm_password_reset_handler(req: Request, res: Response, _: Captures) {
+    let (body, _) = prepare_parameters(req);
+
+    let confirm: PasswordResetConfirm = match serde_json::from_str(&body) {
+        Ok(confirm) => confirm,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+    };
+
+    let errors = confirm.user.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+
+    #[cfg(feature = "diesel")]
+    {
+        let reset = match use_password_reset(&confirm.user.token) {
+            Some(reset) => reset,
+            None => {
+                let errors = ValidationErrors::with("token", "is invalid or expired");
+                return send_errors(res, StatusCode::UnprocessableEntity, errors);
+            }
+        };
+
+        // hashed first, `process` logs its parameters
+        process(res, set_password, (reset.userid, hash_password(&confirm.user.password)))
+    }
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn password_reset_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+
+    let body = format!(r#"{{"user":{{"email": "{}"}}}}"#, email);
+    let res = client
+        .post("http://localhost:6767/api/users/password-reset")
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let reset_token = read_token_from_mail(&email, "/reset-password?token=");
+    let new_password = "dragondragon";
+    let body = format!(
+        r#"{{"user":{{"token": "{}","password": "{}"}}}}"#,
+        reset_token,
+        new_password
+    );
+
+    let res = client
+        .post("http://localhost:6767/api/users/password-reset/confirm")
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    login_jacob(email, new_password.to_string());
+
+    // reset tokens are single use
+    let res = client
+        .post("http://localhost:6767/api/users/password-reset/confirm")
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+}
+
+#[cfg(test)]
+#[test]
+fn password_reset_unknown_email_test() {
+    let client = Client::new();
+
+    let body = format!(r#"{{"user":{{"email": "nobody-{}@jake.jake"}}}}"#, since_the_epoch());
+    let mut res = client
+        .post("http://localhost:6767/api/users/password-reset")
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+
+    assert_eq!(res.status, hyper::Ok);
+    assert_eq!(buffer, "");
+}
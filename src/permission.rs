@@ -0,0 +1,128 @@
+use hyper::server::Response;
+
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Role {
+    User,
+    Moderator,
+    Admin,
+}
+
+/// Values of the Role column, lowest first.
+pub static ROLE_NAMES: &'static [&'static str] = &["user", "moderator", "admin"];
+
+impl Role {
+    pub fn parse(name: &str) -> Option<Role> {
+        match name {
+            "user" => Some(Role::User),
+            "moderator" => Some(Role::Moderator),
+            "admin" => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        ROLE_NAMES[*self as usize]
+    }
+}
+
+/// Mutations that may be done to content one doesn't own, given the role for it.
+pub enum Permission {
+    EditArticle,
+    DeleteArticle,
+    UnpublishArticle,
+    DeleteComment,
+    RestoreContent,
+    ChangeRoles,
+    ManageUsers,
+    ModerateContent,
+    ManageWebhooks,
+}
+
+impl Permission {
+    /// Lowest role allowed to do this to somebody else's content, `None` when only the owner may.
+    fn min_role(&self) -> Option<Role> {
+        match *self {
+            Permission::EditArticle => None,
+            Permission::DeleteArticle => Some(Role::Admin),
+            Permission::UnpublishArticle => Some(Role::Moderator),
+            Permission::DeleteComment => Some(Role::Moderator),
+            Permission::RestoreContent => Some(Role::Admin),
+            Permission::ChangeRoles => Some(Role::Admin),
+            Permission::ManageUsers => Some(Role::Admin),
+            Permission::ModerateContent => Some(Role::Moderator),
+            Permission::ManageWebhooks => Some(Role::Admin),
+        }
+    }
+}
+
+pub fn is_permitted(role: Role, logged_id: i32, permission: &Permission, owner_id: Option<i32>) -> bool {
+    if owner_id == Some(logged_id) {
+        return true;
+    }
+    permission.min_role().map_or(false, |min_role| role >= min_role)
+}
+
+#[cfg(feature = "diesel")]
+pub fn get_role(user_id: i32) -> Role {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    let user_role: String = users
+        .filter(id.eq(user_id))
+        .select(role)
+        .first(&connection)
+        .unwrap_or("user".to_string());
+    Role::parse(&user_role).unwrap_or(Role::User)
+}
+
+/// Answers 403 unless the logged user owns the content (`owner_id`) or has a role
+/// high enough for `permission`, otherwise hands `res` back.
+pub fn require_permission(
+    res: Response,
+    logged_id: i32,
+    permission: Permission,
+    owner_id: Option<i32>,
+) -> Option<Response> {
+    #[cfg(feature = "diesel")]
+    {
+        if !is_permitted(get_role(logged_id), logged_id, &permission, owner_id) {
+            let errors = ValidationErrors::with("role", "is not allowed to do this");
+            send_errors(res, StatusCode::Forbidden, errors);
+            return None;
+        }
+    }
+    Some(res)
+}
+
+/// Sets a role straight in the database, tests have no admin to ask for the first one.
+#[cfg(test)]
+pub fn set_role(user_name: &str, new_role: Role) {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(users.filter(username.eq(user_name)))
+        .set(role.eq(new_role.name()))
+        .execute(&connection)
+        .expect("Error setting role");
+}
+
+#[cfg(test)]
+#[test]
+fn permission_test() {
+    assert!(is_permitted(Role::User, 1, &Permission::EditArticle, Some(1)));
+    assert!(!is_permitted(Role::Admin, 1, &Permission::EditArticle, Some(2)));
+
+    assert!(!is_permitted(Role::User, 1, &Permission::DeleteComment, Some(2)));
+    assert!(is_permitted(Role::Moderator, 1, &Permission::DeleteComment, Some(2)));
+    assert!(is_permitted(Role::Admin, 1, &Permission::DeleteArticle, Some(2)));
+    assert!(!is_permitted(Role::Moderator, 1, &Permission::DeleteArticle, Some(2)));
+    assert!(is_permitted(Role::Moderator, 1, &Permission::UnpublishArticle, Some(2)));
+
+    assert!(!is_permitted(Role::Moderator, 1, &Permission::ChangeRoles, None));
+    assert!(is_permitted(Role::Admin, 1, &Permission::ChangeRoles, None));
+
+    assert_eq!(Role::parse("moderator").map(|r| r.name()), Some("moderator"));
+    assert_eq!(Role::parse("root"), None);
+}
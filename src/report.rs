@@ -0,0 +1,446 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ReportResult {
+    pub report: Report,
+}
+
+/// An open report with the content it points at, as moderators see it in the queue.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ReportEntry {
+    pub report: Report,
+    pub reporter: String,
+    pub article: Article,
+    pub comment: Option<Comment>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ReportsResult {
+    pub reports: Vec<ReportEntry>,
+}
+
+impl Container<ReportEntry> for ReportsResult {
+    fn create_new_with_items(reports: Vec<ReportEntry>) -> ReportsResult {
+        ReportsResult { reports: reports }
+    }
+}
+
+fn reports_result(_: ReportsResult) {}
+
+lazy_static! {
+    static ref HIDE_AFTER_REPORTS : i64 = get_config()
+        .moderation
+        .and_then(|c| c.hide_after_reports)
+        .unwrap_or(3);
+}
+
+#[derive(Debug)]
+pub struct NewReportParams {
+    pub reporter: i32,
+    pub articleid: i32,
+    pub commentid: Option<i32>,
+    pub reason: String,
+}
+
+/// Open reports on an article itself (`comment_id` None) or on one of its comments.
+#[cfg(feature = "diesel")]
+fn get_open_reports(connection: &PgConnection, article_id: i32, comment_id: Option<i32>) -> QueryResult<Vec<Report>> {
+    use schema::reports::dsl::*;
+
+    match comment_id {
+        Some(comment_id) => reports
+            .filter(commentid.eq(comment_id))
+            .filter(resolvedat.is_null())
+            .load(connection),
+        None => reports
+            .filter(articleid.eq(article_id))
+            .filter(commentid.is_null())
+            .filter(resolvedat.is_null())
+            .load(connection),
+    }
+}
+
+/// Sets `hidden` on the reported content. Setting it to what it was takes the row lock reports
+/// on the same content wait for.
+#[cfg(feature = "diesel")]
+fn set_hidden(connection: &PgConnection, article_id: i32, comment_id: Option<i32>, hide: Option<bool>) -> QueryResult<usize> {
+    use schema::articles;
+    use schema::comments;
+
+    match (comment_id, hide) {
+        (Some(comment_id), Some(hide)) => diesel::update(comments::table.filter(comments::id.eq(comment_id)))
+            .set(comments::hidden.eq(hide))
+            .execute(connection),
+        (Some(comment_id), None) => diesel::update(comments::table.filter(comments::id.eq(comment_id)))
+            .set(comments::hidden.eq(comments::hidden))
+            .execute(connection),
+        (None, Some(hide)) => diesel::update(articles::table.filter(articles::id.eq(article_id)))
+            .set(articles::hidden.eq(hide))
+            .execute(connection),
+        (None, None) => diesel::update(articles::table.filter(articles::id.eq(article_id)))
+            .set(articles::hidden.eq(articles::hidden))
+            .execute(connection),
+    }
+}
+
+/// Reports go along when the content they point at is deleted for good.
+#[cfg(feature = "diesel")]
+pub fn delete_reports_for(connection: &PgConnection, article_id: i32, comment_id: Option<i32>) -> QueryResult<usize> {
+    use schema::reports::dsl::*;
+
+    match comment_id {
+        Some(comment_id) => diesel::delete(reports.filter(commentid.eq(comment_id))).execute(connection),
+        None => diesel::delete(reports.filter(articleid.eq(article_id))).execute(connection),
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn add_report(params: NewReportParams) -> Option<ReportResult> {
+    use diesel::pg::upsert::*;
+    use schema::reports;
+
+    let new_report = NewReport {
+        articleid: params.articleid,
+        commentid: params.commentid,
+        reporter: params.reporter,
+        reason: &params.reason,
+        createdat: Utc::now().naive_utc(),
+    };
+
+    let connection = establish_connection();
+    let report = connection.transaction::<_, diesel::result::Error, _>(|| {
+        // reports on the same content take turns, so each one counts all the others
+        set_hidden(&connection, params.articleid, params.commentid, None)?;
+
+        // reporting the same thing twice doesn't count twice, the first report stays
+        diesel::insert(&new_report.on_conflict_do_nothing())
+            .into(reports::table)
+            .execute(&connection)?;
+
+        let open_reports = get_open_reports(&connection, params.articleid, params.commentid)?;
+        if open_reports.len() as i64 >= *HIDE_AFTER_REPORTS {
+            set_hidden(&connection, params.articleid, params.commentid, Some(true))?;
+        }
+        Ok(open_reports.into_iter().find(|r| r.reporter == params.reporter))
+    }).expect("Error saving report");
+
+    report.map(|report| ReportResult { report: report })
+}
+
+fn parse_reason(res: Response, body: &str) -> Option<(Response, String)> {
+    let report: AddReport = match serde_json::from_str(body) {
+        Ok(report) => report,
+        Err(_) => {
+            send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("report", "can't be read"));
+            return None;
+        }
+    };
+
+    let errors = report.report.validate();
+    if !errors.is_empty() {
+        send_errors(res, StatusCode::UnprocessableEntity, errors);
+        return None;
+    }
+    Some((res, report.report.reason))
+}
+
+pub fn report_article_handler(req: Request, res: Response, c: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let (res, reason) = match parse_reason(res, &body) {
+        Some(parsed) => parsed,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/report", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let article = match find_article(slug) {
+            Some(article) => article,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        if article.author == logged_id {
+            return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("article", "is your own"));
+        }
+
+        let params = NewReportParams {
+            reporter: logged_id,
+            articleid: article.id,
+            commentid: None,
+            reason: reason,
+        };
+        process(res, add_report, params)
+    }
+}
+
+pub fn report_comment_handler(req: Request, res: Response, c: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let (res, reason) = match parse_reason(res, &body) {
+        Some(parsed) => parsed,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let url_params = caps[0].replace("/api/articles/", "").replace("/report", "");
+    let parts: Vec<&str> = url_params.split("/comments/").collect();
+    let slug = parts[0];
+    let comment_id = parts.get(1).and_then(|id| id.parse::<i32>().ok()).unwrap_or(0);
+
+    #[cfg(feature = "diesel")]
+    {
+        use schema::comments;
+
+        let connection = establish_connection();
+        let comment: Option<Comment> = find_article(slug).and_then(|article| {
+            comments::table
+                .filter(comments::id.eq(comment_id).and(comments::articleid.eq(article.id)))
+                .filter(comments::deletedat.is_null())
+                .first(&connection)
+                .ok()
+        });
+        let comment = match comment {
+            Some(comment) => comment,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("comment", "not found")),
+        };
+        if comment.author == logged_id {
+            return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("comment", "is your own"));
+        }
+
+        let params = NewReportParams {
+            reporter: logged_id,
+            articleid: comment.articleid,
+            commentid: Some(comment.id),
+            reason: reason,
+        };
+        process(res, add_report, params)
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn get_report_queue(_: ()) -> Vec<ReportEntry> {
+    use schema::{articles, comments, reports, users};
+
+    let connection = establish_connection();
+    let open_reports: Vec<Report> = reports::table
+        .filter(reports::resolvedat.is_null())
+        .order(reports::createdat.asc())
+        .load(&connection)
+        .expect("Error loading reports");
+
+    open_reports
+        .into_iter()
+        .map(|report| {
+            let reporter: String = users::table
+                .filter(users::id.eq(report.reporter))
+                .select(users::username)
+                .first(&connection)
+                .expect("Error loading reporter");
+            let article: Article = articles::table
+                .filter(articles::id.eq(report.articleid))
+                .first(&connection)
+                .expect("Error loading reported article");
+            let comment: Option<Comment> = report.commentid.map(|comment_id| {
+                comments::table
+                    .filter(comments::id.eq(comment_id))
+                    .first(&connection)
+                    .expect("Error loading reported comment")
+            });
+
+            ReportEntry {
+                report: report,
+                reporter: reporter,
+                article: article,
+                comment: comment,
+            }
+        })
+        .collect()
+}
+
+pub fn list_reports_handler(req: Request, res: Response, _: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let res = match require_permission(res, logged_id, Permission::ModerateContent, None) {
+        Some(res) => res,
+        None => return,
+    };
+
+    #[cfg(feature = "diesel")]
+    process_container(res, reports_result, get_report_queue, ());
+}
+
+/// Closes every open report on the same content as `report_id`; removing keeps the
+/// content hidden, dismissing brings it back if reports had hidden it.
+#[cfg(feature = "diesel")]
+fn resolve_reports(params: (i32, &'static str)) -> Option<bool> {
+    use schema::reports::dsl::*;
+
+    let (report_id, outcome) = params;
+    let connection = establish_connection();
+
+    let report: Report = match reports.filter(id.eq(report_id)).first(&connection) {
+        Ok(report) => report,
+        Err(_) => return None,
+    };
+
+    connection.transaction::<_, diesel::result::Error, _>(|| {
+        for open_report in get_open_reports(&connection, report.articleid, report.commentid)? {
+            diesel::update(reports.filter(id.eq(open_report.id)))
+                .set((resolvedat.eq(Some(Utc::now().naive_utc())), resolution.eq(Some(outcome))))
+                .execute(&connection)?;
+        }
+        set_hidden(&connection, report.articleid, report.commentid, Some(outcome == "removed"))
+    }).expect("Error resolving reports");
+    None
+}
+
+fn moderation_handler(req: Request, res: Response, c: Captures, outcome: &'static str) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+    let res = match require_permission(res, logged_id, Permission::ModerateContent, None) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let report_id = caps[0]
+        .replace("/api/reports/", "")
+        .split('/')
+        .next()
+        .and_then(|id| id.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    #[cfg(feature = "diesel")]
+    process(res, resolve_reports, (report_id, outcome));
+}
+
+pub fn dismiss_report_handler(req: Request, res: Response, c: Captures) {
+    moderation_handler(req, res, c, "dismissed")
+}
+
+pub fn remove_reported_handler(req: Request, res: Response, c: Captures) {
+    moderation_handler(req, res, c, "removed")
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+fn report_as_new_user(url: &str) -> hyper::client::Response {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+
+    client
+        .post(url)
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"report": {"reason": "spam"}}"#)
+        .send()
+        .unwrap()
+}
+
+#[cfg(test)]
+#[test]
+fn report_hides_article_test() {
+    let client = Client::new();
+    let (author_jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}/report", slug);
+
+    // authors can't report what they wrote
+    let res = client
+        .post(&url)
+        .header(Authorization(Bearer { token: author_jwt }))
+        .body(r#"{"report": {"reason": "spam"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    // reporting twice keeps the first report and counts once
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let mut ids = Vec::new();
+    for _ in 0..2 {
+        let mut res = client
+            .post(&url)
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .body(r#"{"report": {"reason": "spam"}}"#)
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::Ok);
+
+        let result: ReportResult = serde_json::from_str(&buffer).unwrap();
+        ids.push(result.report.id);
+    }
+    assert_eq!(ids[0], ids[1]);
+    let article_id = find_article(&slug).unwrap().id;
+    assert_eq!(get_open_reports(&establish_connection(), article_id, None).unwrap().len(), 1);
+
+    for _ in 0..*HIDE_AFTER_REPORTS {
+        let mut res = report_as_new_user(&url);
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::Ok);
+
+        let result: ReportResult = serde_json::from_str(&buffer).unwrap();
+        assert_eq!(result.report.reason, "spam");
+    }
+    assert!(find_article(&slug).unwrap().hidden);
+
+    let (moderator_name, moderator_email) = register_jacob();
+    set_role(&moderator_name, Role::Moderator);
+    let moderator_jwt = login_jacob(moderator_email, user::JACOB_PASSWORD.to_string());
+
+    let mut res = client
+        .get("http://localhost:6767/api/reports")
+        .header(Authorization(Bearer { token: moderator_jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let queue: ReportsResult = serde_json::from_str(&buffer).unwrap();
+    let entry = queue.reports.iter().find(|e| e.article.slug == slug).unwrap();
+    assert!(entry.comment.is_none());
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/reports/{}/dismiss", entry.report.id))
+        .header(Authorization(Bearer { token: moderator_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    assert!(!find_article(&slug).unwrap().hidden);
+    assert!(get_open_reports(&establish_connection(), entry.article.id, None).unwrap().is_empty());
+
+    // the queue is for moderators only
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let res = client
+        .get("http://localhost:6767/api/reports")
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+}
@@ -0,0 +1,506 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use slug::slugify;
+
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct RevisionResult {
+    pub revision: ArticleRevision,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct RevisionsResult {
+    pub revisions: Vec<ArticleRevision>,
+}
+
+impl Container<ArticleRevision> for RevisionsResult {
+    fn create_new_with_items(revisions: Vec<ArticleRevision>) -> RevisionsResult {
+        RevisionsResult { revisions: revisions }
+    }
+}
+
+fn revisions_result(_: RevisionsResult) {}
+
+/// One line of a diff, `op` is "+" for added, "-" for removed and " " for kept.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug, PartialEq)]
+pub struct DiffLine {
+    pub op: String,
+    pub text: String,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct RevisionDiff {
+    pub from: String,
+    pub to: String,
+    pub title: Vec<DiffLine>,
+    pub description: Vec<DiffLine>,
+    pub body: Vec<DiffLine>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct DiffResult {
+    pub diff: RevisionDiff,
+}
+
+/// Revisions are referred to by number, "current" stands for the article as it is now.
+pub static CURRENT_REVISION: &'static str = "current";
+
+/// Texts longer than this many lines aren't compared, the diff takes time with both lengths.
+pub static MAX_DIFF_LINES: usize = 5000;
+
+fn diff_line(op: &str, text: &str) -> DiffLine {
+    DiffLine {
+        op: op.to_string(),
+        text: text.to_string(),
+    }
+}
+
+/// Lengths of the longest common subsequences of all of `old` and each start of `new`.
+fn common_lengths(old: &[&str], new: &[&str]) -> Vec<usize> {
+    let mut lengths = vec![0usize; new.len() + 1];
+    for old_line in old {
+        let mut diagonal = 0;
+        for j in 0..new.len() {
+            let above = lengths[j + 1];
+            lengths[j + 1] = if *old_line == new[j] {
+                diagonal + 1
+            } else {
+                ::std::cmp::max(lengths[j], above)
+            };
+            diagonal = above;
+        }
+    }
+    lengths
+}
+
+/// Hirschberg's split: where `new` divides so both halves of `old` keep their longest
+/// common subsequence with it, in memory linear in the lengths.
+fn diff_slices(old: &[&str], new: &[&str], result: &mut Vec<DiffLine>) {
+    if old.is_empty() {
+        result.extend(new.iter().map(|text| diff_line("+", text)));
+    } else if new.is_empty() {
+        result.extend(old.iter().map(|text| diff_line("-", text)));
+    } else if old.len() == 1 {
+        match new.iter().position(|text| *text == old[0]) {
+            Some(j) => {
+                result.extend(new[..j].iter().map(|text| diff_line("+", text)));
+                result.push(diff_line(" ", old[0]));
+                result.extend(new[j + 1..].iter().map(|text| diff_line("+", text)));
+            }
+            None => {
+                result.push(diff_line("-", old[0]));
+                result.extend(new.iter().map(|text| diff_line("+", text)));
+            }
+        }
+    } else {
+        let middle = old.len() / 2;
+        let reversed_old: Vec<&str> = old[middle..].iter().rev().cloned().collect();
+        let reversed_new: Vec<&str> = new.iter().rev().cloned().collect();
+        let front = common_lengths(&old[..middle], new);
+        let back = common_lengths(&reversed_old, &reversed_new);
+
+        let split = (0..new.len() + 1)
+            .max_by_key(|&j| (front[j] + back[new.len() - j], ::std::cmp::Reverse(j)))
+            .unwrap_or(0);
+        diff_slices(&old[..middle], &new[..split], result);
+        diff_slices(&old[middle..], &new[split..], result);
+    }
+}
+
+/// Line by line diff from `old` to `new`, along their longest common subsequence.
+pub fn diff_lines(old: &str, new: &str) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+
+    let mut result = Vec::new();
+    diff_slices(&old, &new, &mut result);
+    result
+}
+
+/// Locks the article's row until the end of the transaction and reads it as it is then.
+/// diesel has no FOR UPDATE yet, an update that changes nothing takes the same lock.
+#[cfg(feature = "diesel")]
+pub fn lock_article(connection: &PgConnection, article_id: i32) -> QueryResult<Article> {
+    use schema::articles::dsl::*;
+
+    diesel::update(articles.filter(id.eq(article_id)))
+        .set(hidden.eq(hidden))
+        .get_result(connection)
+}
+
+/// Keeps `article` as it is now as its next revision, before it gets changed. Call it inside a
+/// transaction, on the article `lock_article` returned, so concurrent edits are numbered one after
+/// the other and each revision is what the edit replaced.
+#[cfg(feature = "diesel")]
+pub fn save_revision(connection: &PgConnection, article: &Article, editor_id: i32) -> QueryResult<()> {
+    use schema::articlerevisions::dsl::*;
+
+    let last: Option<i32> = articlerevisions
+        .filter(articleid.eq(article.id))
+        .select(number)
+        .order(number.desc())
+        .first(connection)
+        .optional()?;
+
+    let new_revision = NewArticleRevision {
+        articleid: article.id,
+        number: last.unwrap_or(0) + 1,
+        title: &article.title,
+        description: &article.description,
+        body: &article.body,
+        editorid: editor_id,
+        createdat: Utc::now().naive_utc(),
+    };
+    diesel::insert(&new_revision)
+        .into(articlerevisions)
+        .execute(connection)?;
+    Ok(())
+}
+
+/// Revisions go along when their article is deleted.
+#[cfg(feature = "diesel")]
+pub fn delete_revisions_for(connection: &PgConnection, article_id: i32) -> QueryResult<usize> {
+    use schema::articlerevisions::dsl::*;
+
+    diesel::delete(articlerevisions.filter(articleid.eq(article_id))).execute(connection)
+}
+
+#[cfg(feature = "diesel")]
+fn find_revision(article_id: i32, revision_number: i32) -> Option<ArticleRevision> {
+    use schema::articlerevisions::dsl::*;
+
+    let connection = establish_connection();
+    articlerevisions
+        .filter(articleid.eq(article_id))
+        .filter(number.eq(revision_number))
+        .first(&connection)
+        .ok()
+}
+
+/// Title, description and body of a revision, or of the article itself for "current".
+#[cfg(feature = "diesel")]
+fn find_version(article: &Article, reference: &str) -> Option<(String, String, String)> {
+    if reference == CURRENT_REVISION {
+        return Some((article.title.to_owned(), article.description.to_owned(), article.body.to_owned()));
+    }
+    reference
+        .parse::<i32>()
+        .ok()
+        .and_then(|revision_number| find_revision(article.id, revision_number))
+        .map(|revision| (revision.title, revision.description, revision.body))
+}
+
+/// Splits `/api/articles/:slug/revisions/...` into the slug and what follows `revisions`.
+fn parse_revision_path(path: &str) -> (String, Vec<String>) {
+    let path = path.replace("/api/articles/", "");
+    let mut parts = path.splitn(2, "/revisions");
+    let slug = parts.next().unwrap_or("").to_string();
+    let rest = parts
+        .next()
+        .unwrap_or("")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_string())
+        .collect();
+    (slug, rest)
+}
+
+/// Revisions are the author's own business, answers 404 or 403 otherwise.
+#[cfg(feature = "diesel")]
+fn require_article_author(res: Response, logged_id: i32, slug: &str) -> Option<(Response, Article)> {
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return None,
+    };
+    let article = match find_article(slug) {
+        Some(article) => article,
+        None => {
+            send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found"));
+            return None;
+        }
+    };
+    let res = match require_permission(res, logged_id, Permission::EditArticle, Some(article.author)) {
+        Some(res) => res,
+        None => return None,
+    };
+    Some((res, article))
+}
+
+#[cfg(feature = "diesel")]
+fn get_revisions(article_id: i32) -> Vec<ArticleRevision> {
+    use schema::articlerevisions::dsl::*;
+
+    let connection = establish_connection();
+    articlerevisions
+        .filter(articleid.eq(article_id))
+        .order(number.desc())
+        .load(&connection)
+        .expect("Error loading article revisions")
+}
+
+pub fn list_revisions_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let caps = c.unwrap();
+    let (slug, _) = parse_revision_path(&caps[0]);
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, article) = match require_article_author(res, logged_id, &slug) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        process_container(res, revisions_result, get_revisions, article.id);
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn get_revision(revision: ArticleRevision) -> Option<RevisionResult> {
+    Some(RevisionResult { revision: revision })
+}
+
+pub fn get_revision_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let caps = c.unwrap();
+    let (slug, rest) = parse_revision_path(&caps[0]);
+    let revision_number = rest.get(0).and_then(|n| n.parse::<i32>().ok()).unwrap_or(0);
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, article) = match require_article_author(res, logged_id, &slug) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        match find_revision(article.id, revision_number) {
+            Some(revision) => process(res, get_revision, revision),
+            None => send_errors(res, StatusCode::NotFound, ValidationErrors::with("revision", "not found")),
+        }
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn diff_versions(params: (String, String, (String, String, String), (String, String, String))) -> Option<DiffResult> {
+    let (from, to, old, new) = params;
+    Some(DiffResult {
+        diff: RevisionDiff {
+            from: from,
+            to: to,
+            title: diff_lines(&old.0, &new.0),
+            description: diff_lines(&old.1, &new.1),
+            body: diff_lines(&old.2, &new.2),
+        },
+    })
+}
+
+/// Whether any of title, description and body is too long to diff.
+fn too_long_to_diff(version: &(String, String, String)) -> bool {
+    [&version.0, &version.1, &version.2]
+        .iter()
+        .any(|text| text.lines().count() > MAX_DIFF_LINES)
+}
+
+/// `GET /api/articles/:slug/revisions/:from/diff/:to`, either end may be "current".
+pub fn revision_diff_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let caps = c.unwrap();
+    let (slug, rest) = parse_revision_path(&caps[0]);
+    let from = rest.get(0).cloned().unwrap_or_default();
+    let to = rest.get(2).cloned().unwrap_or_default();
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, article) = match require_article_author(res, logged_id, &slug) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        match (find_version(&article, &from), find_version(&article, &to)) {
+            (Some(ref old), Some(ref new)) if too_long_to_diff(old) || too_long_to_diff(new) => {
+                let errors = ValidationErrors::with("revision", &format!("is longer than {} lines", MAX_DIFF_LINES));
+                send_errors(res, StatusCode::UnprocessableEntity, errors)
+            }
+            (Some(old), Some(new)) => process(res, diff_versions, (from, to, old, new)),
+            _ => send_errors(res, StatusCode::NotFound, ValidationErrors::with("revision", "not found")),
+        }
+    }
+}
+
+/// Puts a revision back in place, the version it replaces becomes a revision itself.
+#[cfg(feature = "diesel")]
+fn restore_revision(params: (Article, ArticleRevision, i32)) -> Option<ArticleResult> {
+    use schema::articles::dsl::*;
+
+    let (article, revision, editor_id) = params;
+
+    let connection = establish_connection();
+    let new_slug = slugify(revision.title.to_owned());
+    let stats = article_stats(&revision.description, &revision.body);
+    connection
+        .transaction::<_, diesel::result::Error, _>(|| {
+            let current = lock_article(&connection, article.id)?;
+            save_revision(&connection, &current, editor_id)?;
+            diesel::update(articles.filter(id.eq(article.id)))
+                .set((
+                    slug.eq(&new_slug),
+                    title.eq(&revision.title),
+                    description.eq(&revision.description),
+                    body.eq(&revision.body),
+                    updatedat.eq(Some(Utc::now().naive_utc())),
+                    wordcount.eq(stats.word_count),
+                    readingtimeminutes.eq(stats.reading_time_minutes),
+                    excerpt.eq(&stats.excerpt),
+                ))
+                .execute(&connection)?;
+            Ok(())
+        })
+        .expect("Error restoring article revision");
+
+    get_advanced_article(&new_slug)
+}
+
+pub fn restore_revision_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let caps = c.unwrap();
+    let (slug, rest) = parse_revision_path(&caps[0]);
+    let revision_number = rest.get(0).and_then(|n| n.parse::<i32>().ok()).unwrap_or(0);
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, article) = match require_article_author(res, logged_id, &slug) {
+            Some(allowed) => allowed,
+            None => return,
+        };
+        match find_revision(article.id, revision_number) {
+            Some(ref revision) if slug_taken(&slugify(revision.title.to_owned()), article.id) => {
+                let errors = ValidationErrors::with("title", "is used by another article by now");
+                send_errors(res, StatusCode::UnprocessableEntity, errors)
+            }
+            Some(revision) => process(res, restore_revision, (article, revision, logged_id)),
+            None => send_errors(res, StatusCode::NotFound, ValidationErrors::with("revision", "not found")),
+        }
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn diff_test() {
+    let diff = diff_lines("a\nb\nc", "a\nc\nd");
+    let ops: Vec<(&str, &str)> = diff.iter().map(|l| (l.op.as_str(), l.text.as_str())).collect();
+    assert_eq!(ops, vec![(" ", "a"), ("-", "b"), (" ", "c"), ("+", "d")]);
+
+    assert!(diff_lines("same", "same").iter().all(|l| l.op == " "));
+    assert_eq!(diff_lines("", "new").len(), 1);
+
+    let old: Vec<String> = (0..1000).map(|n| n.to_string()).collect();
+    let new: Vec<String> = (0..1000).filter(|n| n % 3 != 0).map(|n| n.to_string()).collect();
+    let diff = diff_lines(&old.join("\n"), &new.join("\n"));
+    assert_eq!(diff.len(), 1000);
+    assert_eq!(diff.iter().filter(|l| l.op == "-").count(), 334);
+    assert!(diff.iter().all(|l| l.op != "+"));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn revision_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+
+    let res = client
+        .put(&format!("http://localhost:6767/api/articles/{}", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(r#"{"article": {"body": "You have to believe\nand train"}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles/{}/revisions", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let revisions: RevisionsResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(revisions.revisions.len(), 1);
+    assert_eq!(revisions.revisions[0].body, "You have to believe");
+
+    let mut res = client
+        .get(&format!("http://localhost:6767/api/articles/{}/revisions/1/diff/current", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: DiffResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.diff.body.last().map(|l| l.op.as_str()), Some("+"));
+
+    let mut res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/revisions/1/restore", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let restored: ArticleResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(restored.article.body, "You have to believe");
+
+    // somebody else can't look at the history
+    let (_, email) = register_jacob();
+    let other_jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let res = client
+        .get(&format!("http://localhost:6767/api/articles/{}/revisions", slug))
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+}
+
+#[cfg(test)]
+#[test]
+fn restore_taken_title_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+    let old_title = get_article(&slug).title;
+
+    let mut res = client
+        .put(&format!("http://localhost:6767/api/articles/{}", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(&format!(r#"{{"article": {{"title": "{} renamed"}}}}"#, old_title))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let renamed: ArticleResult = serde_json::from_str(&buffer).unwrap();
+
+    // somebody else takes the old title, and with it the old slug
+    let (_, email) = register_jacob();
+    let other_jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: other_jwt }))
+        .body(&format!(r#"{{"article": {{"title": "{}","description": "Mine now","body": "Mine"}}}}"#, old_title))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let mut res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/revisions/1/restore", renamed.article.slug))
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert!(result.errors.contains_key("title"));
+    assert_eq!(find_article(&renamed.article.slug).unwrap().title, renamed.article.title);
+}
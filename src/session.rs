@@ -0,0 +1,268 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use crypto::digest::Digest;
+use crypto::sha2::Sha256;
+
+use rand::Rng;
+use rand::os::OsRng;
+
+use rustc_serialize::hex::ToHex;
+
+use super::*;
+
+fn get_session_config() -> (u64, i64) {
+    match get_config().session {
+        Some(config) => (
+            config.access_token_minutes.unwrap_or(15) * 60,
+            config.refresh_token_days.unwrap_or(30),
+        ),
+        None => (15 * 60, 30),
+    }
+}
+
+lazy_static! {
+    pub static ref ACCESS_TOKEN_SECONDS : u64 = get_session_config().0;
+    pub static ref REFRESH_TOKEN_DAYS : i64 = get_session_config().1;
+}
+
+/// Only the SHA-256 of a token is stored, the token itself is given out once.
+pub fn hash_token(token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input_str(token);
+    hasher.result_str()
+}
+
+pub fn new_random_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng::new().unwrap().fill_bytes(&mut bytes);
+    bytes.to_hex()
+}
+
+#[cfg(feature = "diesel")]
+pub fn create_session(user_id: i32) -> String {
+    use schema::sessions;
+
+    let refresh_token = new_random_token();
+
+    let now = Utc::now().naive_utc();
+    let new_session = NewSession {
+        userid: user_id,
+        tokenhash: &hash_token(&refresh_token),
+        createdat: now,
+        expiresat: now + chrono::Duration::days(*REFRESH_TOKEN_DAYS),
+    };
+
+    let connection = establish_connection();
+    let _session: Session = diesel::insert(&new_session)
+        .into(sessions::table)
+        .get_result(&connection)
+        .expect("Error saving new session");
+    refresh_token
+}
+
+/// Revokes the session of an active refresh token and hands it back. Checked and revoked in
+/// one statement, so two requests racing with the same token can't both spend it.
+#[cfg(feature = "diesel")]
+fn spend_session(refresh_token: &str) -> Option<Session> {
+    use schema::sessions::dsl::*;
+
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+    diesel::update(
+        sessions
+            .filter(tokenhash.eq(hash_token(refresh_token)))
+            .filter(revokedat.is_null())
+            .filter(expiresat.gt(now)),
+    ).set(revokedat.eq(Some(now)))
+        .get_result(&connection)
+        .ok()
+}
+
+#[cfg(feature = "diesel")]
+fn revoke_session(user_id: i32, refresh_token_hash: &str) {
+    use schema::sessions::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(
+        sessions
+            .filter(tokenhash.eq(refresh_token_hash))
+            .filter(userid.eq(user_id))
+            .filter(revokedat.is_null()),
+    ).set(revokedat.eq(Some(Utc::now().naive_utc())))
+        .execute(&connection)
+        .expect("Error revoking session");
+}
+
+/// Revokes every refresh token of the user and bumps the token version, which
+/// makes `login` reject all access tokens issued so far.
+#[cfg(feature = "diesel")]
+pub fn revoke_all_sessions(user_id: i32) {
+    use schema::sessions;
+    use schema::users;
+
+    let connection = establish_connection();
+    diesel::update(
+        sessions::table.filter(sessions::userid.eq(user_id).and(sessions::revokedat.is_null())),
+    ).set(sessions::revokedat.eq(Some(Utc::now().naive_utc())))
+        .execute(&connection)
+        .expect("Error revoking sessions");
+
+    diesel::update(users::table.filter(users::id.eq(user_id)))
+        .set(users::tokenversion.eq(users::tokenversion + 1))
+        .execute(&connection)
+        .expect("Error bumping token version");
+}
+
+pub fn refresh_handler(req: Request, mut res: Response, _: Captures) {
+    let (body, _) = prepare_parameters(req);
+
+    let refresh: RefreshDetails = serde_json::from_str(&body).unwrap_or_default();
+
+    #[cfg(feature = "diesel")]
+    {
+        // refresh tokens are single use, every refresh hands out a new one
+        let session = match spend_session(&refresh.refreshToken) {
+            Some(session) => session,
+            None => {
+                let errors = ValidationErrors::with("refreshToken", "is invalid or expired");
+                return send_errors(res, StatusCode::Unauthorized, errors);
+            }
+        };
+
+        if is_suspended(session.userid) {
+            return send_errors(res, StatusCode::Forbidden, ValidationErrors::with("user", "is suspended"));
+        }
+
+        let user = get_user_by_id(session.userid).unwrap().user;
+        let refresh_token = create_session(user.id);
+        let access_token = new_token(user.id, user.tokenversion).unwrap();
+
+        res.headers_mut().set(Authorization(Bearer { token: access_token }));
+        send_result(
+            res,
+            LoginResult {
+                user: user,
+                refreshToken: refresh_token,
+            },
+        )
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn logout(params: (i32, bool, String)) -> Option<bool> {
+    let (logged_id, everywhere, refresh_token_hash) = params;
+
+    if everywhere {
+        revoke_all_sessions(logged_id);
+    } else {
+        revoke_session(logged_id, &refresh_token_hash);
+    }
+    None
+}
+
+pub fn logout_handler(req: Request, res: Response, _: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let logout_details: RefreshDetails = serde_json::from_str(&body).unwrap_or_default();
+
+    // only the hash goes through `process`, which logs its parameters
+    #[cfg(feature = "diesel")]
+    process(res, logout, (logged_id, logout_details.everywhere, hash_token(&logout_details.refreshToken)));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+pub fn login_with_refresh_token(email: &str) -> (String, String) {
+    let client = Client::new();
+
+    let body = format!(
+        r#"{{"user":{{"email": "{}","password": "{}"}}}}"#,
+        email,
+        user::JACOB_PASSWORD
+    );
+
+    let mut res = client
+        .post("http://localhost:6767/api/users/login")
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let login: LoginResult = serde_json::from_str(&buffer).unwrap();
+    let answer: serde_json::Value = serde_json::from_str(&buffer).unwrap();
+    assert!(answer["user"].get("token").is_none());
+    let token = res.headers.get::<Authorization<Bearer>>().unwrap();
+    (token.0.token.to_owned(), login.refreshToken)
+}
+
+#[cfg(test)]
+#[test]
+fn refresh_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+    let (_, refresh_token) = login_with_refresh_token(&email);
+
+    let body = format!(r#"{{"refreshToken": "{}"}}"#, refresh_token);
+
+    let mut res = client
+        .post("http://localhost:6767/api/users/refresh")
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let refreshed: LoginResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(refreshed.user.email, email);
+    assert!(refreshed.refreshToken != refresh_token);
+
+    // the old refresh token was used up
+    let res = client
+        .post("http://localhost:6767/api/users/refresh")
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Unauthorized);
+}
+
+#[cfg(test)]
+#[test]
+fn logout_everywhere_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+    let (jwt, _) = login_with_refresh_token(&email);
+    let (other_jwt, other_refresh_token) = login_with_refresh_token(&email);
+
+    let res = client
+        .post("http://localhost:6767/api/users/logout")
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"everywhere": true}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client
+        .get("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Unauthorized);
+
+    let body = format!(r#"{{"refreshToken": "{}"}}"#, other_refresh_token);
+    let res = client
+        .post("http://localhost:6767/api/users/refresh")
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Unauthorized);
+}
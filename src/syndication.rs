@@ -0,0 +1,364 @@
+use hyper::header::{ETag, EntityTag, IfNoneMatch};
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+lazy_static! {
+    static ref FEED_TITLE : String = get_config()
+        .feeds
+        .and_then(|c| c.title)
+        .unwrap_or("Conduit".to_string());
+    /// Front end address entries link to, the mail links' one unless set.
+    static ref SITE_URL : String = get_config()
+        .feeds
+        .and_then(|c| c.site_url)
+        .unwrap_or(MAIL.link_base.to_owned());
+    /// Public address of the API itself, the feeds' own links start with it.
+    static ref API_URL : String = get_config()
+        .feeds
+        .and_then(|c| c.api_url)
+        .unwrap_or("http://localhost:6767".to_string());
+    static ref FEED_SIZE : usize = get_config()
+        .feeds
+        .and_then(|c| c.size)
+        .unwrap_or(20);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedFormat {
+    Atom,
+    Rss,
+}
+
+/// An article as it goes into a feed, its body already rendered and sanitized.
+#[derive(Debug)]
+pub struct FeedEntry {
+    pub title: String,
+    pub link: String,
+    pub published: NaiveDateTime,
+    pub updated: NaiveDateTime,
+    pub author: String,
+    pub summary: String,
+    pub content_html: String,
+    pub tags: Vec<String>,
+}
+
+pub fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn rfc3339(time: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(time, Utc).to_rfc3339()
+}
+
+fn rfc2822(time: NaiveDateTime) -> String {
+    DateTime::<Utc>::from_utc(time, Utc).to_rfc2822()
+}
+
+/// The latest change among the entries, the epoch for an empty feed so it stays the same.
+fn feed_updated(entries: &[FeedEntry]) -> NaiveDateTime {
+    entries
+        .iter()
+        .map(|entry| entry.updated)
+        .max()
+        .unwrap_or(NaiveDateTime::from_timestamp(0, 0))
+}
+
+pub fn render_atom(title: &str, site_link: &str, self_link: &str, entries: &[FeedEntry]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml.push_str(&format!("  <title>{}</title>\n", xml_escape(title)));
+    xml.push_str(&format!("  <id>{}</id>\n", xml_escape(self_link)));
+    xml.push_str(&format!("  <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", xml_escape(site_link)));
+    xml.push_str(&format!("  <link rel=\"self\" href=\"{}\"/>\n", xml_escape(self_link)));
+    xml.push_str(&format!("  <updated>{}</updated>\n", rfc3339(feed_updated(entries))));
+
+    for entry in entries {
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", xml_escape(&entry.title)));
+        xml.push_str(&format!("    <id>{}</id>\n", xml_escape(&entry.link)));
+        xml.push_str(&format!("    <link rel=\"alternate\" type=\"text/html\" href=\"{}\"/>\n", xml_escape(&entry.link)));
+        xml.push_str(&format!("    <published>{}</published>\n", rfc3339(entry.published)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", rfc3339(entry.updated)));
+        xml.push_str(&format!("    <author><name>{}</name></author>\n", xml_escape(&entry.author)));
+        for tag in &entry.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", xml_escape(tag)));
+        }
+        xml.push_str(&format!("    <summary>{}</summary>\n", xml_escape(&entry.summary)));
+        xml.push_str(&format!("    <content type=\"html\">{}</content>\n", xml_escape(&entry.content_html)));
+        xml.push_str("  </entry>\n");
+    }
+
+    xml.push_str("</feed>\n");
+    xml
+}
+
+pub fn render_rss(title: &str, site_link: &str, entries: &[FeedEntry]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<rss version=\"2.0\">\n");
+    xml.push_str("  <channel>\n");
+    xml.push_str(&format!("    <title>{}</title>\n", xml_escape(title)));
+    xml.push_str(&format!("    <link>{}</link>\n", xml_escape(site_link)));
+    xml.push_str(&format!("    <description>{}</description>\n", xml_escape(title)));
+    xml.push_str(&format!("    <lastBuildDate>{}</lastBuildDate>\n", rfc2822(feed_updated(entries))));
+
+    for entry in entries {
+        xml.push_str("    <item>\n");
+        xml.push_str(&format!("      <title>{}</title>\n", xml_escape(&entry.title)));
+        xml.push_str(&format!("      <link>{}</link>\n", xml_escape(&entry.link)));
+        xml.push_str(&format!("      <guid isPermaLink=\"true\">{}</guid>\n", xml_escape(&entry.link)));
+        xml.push_str(&format!("      <pubDate>{}</pubDate>\n", rfc2822(entry.published)));
+        for tag in &entry.tags {
+            xml.push_str(&format!("      <category>{}</category>\n", xml_escape(tag)));
+        }
+        xml.push_str(&format!("      <description>{}</description>\n", xml_escape(&entry.content_html)));
+        xml.push_str("    </item>\n");
+    }
+
+    xml.push_str("  </channel>\n");
+    xml.push_str("</rss>\n");
+    xml
+}
+
+/// The newest published articles, of one author or with one tag when given, in the order
+/// the article list shows them to someone logged out.
+#[cfg(feature = "diesel")]
+fn get_feed_entries(tag: &str, author: &str) -> Vec<FeedEntry> {
+    let latest = get_articles_by_filter(FilterParams {
+        tag: tag,
+        author: author,
+        favorited: "",
+        offset: 0,
+        limit: *FEED_SIZE as i32,
+        viewer: 0,
+        drafts: false,
+        following: false,
+    });
+
+    latest
+        .into_iter()
+        .filter_map(|article| {
+            let author = match get_user_by_id(article.author) {
+                Some(result) => result.user.username,
+                None => return None,
+            };
+            let tags = get_advanced_article(&article.slug).map_or(Vec::new(), |result| result.article.tagList);
+            // scheduled articles come out when their time comes, not when they were written
+            let published = article.publishAt.unwrap_or(article.createdAt);
+
+            Some(FeedEntry {
+                link: format!("{}/article/{}", *SITE_URL, article.slug),
+                published: published,
+                updated: article.updatedAt.map_or(published, |updated| updated.max(published)),
+                author: author,
+                content_html: article_body_html(article.id, &article.body),
+                summary: article.description,
+                title: article.title,
+                tags: tags,
+            })
+        })
+        .collect()
+}
+
+/// Where the feed itself lives. Taken from the configured API address, a client's Host header
+/// would end up in the cached feed everyone else gets.
+fn self_link(req: &Request) -> String {
+    format!("{}{}", *API_URL, req.uri)
+}
+
+/// Sends the feed with an ETag of its content, or just 304 when the client has it already.
+fn send_feed(mut res: Response, if_none_match: Option<IfNoneMatch>, format: FeedFormat, xml: String) {
+    let etag = EntityTag::strong(hash_token(&xml));
+
+    res.headers_mut().set(AccessControlAllowOrigin::Any);
+    res.headers_mut().set(ETag(etag.clone()));
+
+    let unchanged = match if_none_match {
+        Some(IfNoneMatch::Any) => true,
+        Some(IfNoneMatch::Items(ref tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        None => false,
+    };
+    if unchanged {
+        *res.status_mut() = StatusCode::NotModified;
+        res.send(b"").unwrap();
+        return;
+    }
+
+    let sub_level = match format {
+        FeedFormat::Atom => "atom+xml",
+        FeedFormat::Rss => "rss+xml",
+    };
+    res.headers_mut().set(ContentType(Mime(
+        TopLevel::Application,
+        SubLevel::Ext(sub_level.to_owned()),
+        vec![(Attr::Charset, Value::Utf8)],
+    )));
+    res.send(xml.as_bytes()).unwrap();
+}
+
+fn global_feed(req: Request, res: Response, format: FeedFormat) {
+    let if_none_match = req.headers.get::<IfNoneMatch>().cloned();
+    let self_link = self_link(&req);
+
+    #[cfg(feature = "diesel")]
+    {
+        let entries = get_feed_entries("", "");
+        let site_link = format!("{}/", *SITE_URL);
+        let xml = match format {
+            FeedFormat::Atom => render_atom(&FEED_TITLE, &site_link, &self_link, &entries),
+            FeedFormat::Rss => render_rss(&FEED_TITLE, &site_link, &entries),
+        };
+        send_feed(res, if_none_match, format, xml);
+    }
+}
+
+pub fn rss_feed_handler(req: Request, res: Response, _: Captures) {
+    global_feed(req, res, FeedFormat::Rss)
+}
+
+pub fn atom_feed_handler(req: Request, res: Response, _: Captures) {
+    global_feed(req, res, FeedFormat::Atom)
+}
+
+pub fn author_feed_handler(req: Request, res: Response, c: Captures) {
+    let if_none_match = req.headers.get::<IfNoneMatch>().cloned();
+    let self_link = self_link(&req);
+
+    let caps = c.unwrap();
+    let path = caps[0].replace("/api/profiles/", "");
+    let user_name = path.splitn(2, '?').next().unwrap_or("").replace("/feed.atom", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        if get_user_by_name(&user_name).is_none() {
+            return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found"));
+        }
+
+        let entries = get_feed_entries("", &user_name);
+        let title = format!("{} - {}", *FEED_TITLE, user_name);
+        let site_link = format!("{}/profile/{}", *SITE_URL, user_name);
+        send_feed(res, if_none_match, FeedFormat::Atom, render_atom(&title, &site_link, &self_link, &entries));
+    }
+}
+
+pub fn tag_feed_handler(req: Request, res: Response, c: Captures) {
+    let if_none_match = req.headers.get::<IfNoneMatch>().cloned();
+    let self_link = self_link(&req);
+
+    let caps = c.unwrap();
+    let path = caps[0].replace("/api/tags/", "");
+    let tag = path.splitn(2, '?').next().unwrap_or("").replace("/feed.atom", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let entries = get_feed_entries(&tag, "");
+        let title = format!("{} - {}", *FEED_TITLE, tag);
+        let site_link = format!("{}/", *SITE_URL);
+        send_feed(res, if_none_match, FeedFormat::Atom, render_atom(&title, &site_link, &self_link, &entries));
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn render_feed_test() {
+    assert_eq!(xml_escape("<p>Tom & \"Jerry\"</p>"), "&lt;p&gt;Tom &amp; &quot;Jerry&quot;&lt;/p&gt;");
+
+    let published = NaiveDateTime::from_timestamp(1514929891, 0);
+    let entries = vec![
+        FeedEntry {
+            title: "How to train your dragon".to_string(),
+            link: "http://localhost:4100/article/how-to-train-your-dragon".to_string(),
+            published: published,
+            updated: published + chrono::Duration::hours(1),
+            author: "jake".to_string(),
+            summary: "Ever wonder how?".to_string(),
+            content_html: "<p>You have to <em>believe</em></p>\n".to_string(),
+            tags: vec!["dragons".to_string()],
+        },
+    ];
+
+    let atom = render_atom("Conduit", "http://localhost:4100/", "http://localhost:6767/api/articles.atom", &entries);
+    assert!(atom.contains("<updated>2018-01-02T22:51:31+00:00</updated>\n  <entry>"));
+    assert!(atom.contains("<content type=\"html\">&lt;p&gt;You have to &lt;em&gt;believe&lt;/em&gt;&lt;/p&gt;\n</content>"));
+    assert!(atom.contains("<category term=\"dragons\"/>"));
+
+    let rss = render_rss("Conduit", "http://localhost:4100/", &entries);
+    assert!(rss.contains("<pubDate>Tue, 02 Jan 2018 21:51:31 +0000</pubDate>"));
+    assert!(rss.contains("<guid isPermaLink=\"true\">http://localhost:4100/article/how-to-train-your-dragon</guid>"));
+
+    // an empty feed stays the same until something is published
+    assert_eq!(render_rss("Conduit", "/", &[]), render_rss("Conduit", "/", &[]));
+}
+
+#[cfg(test)]
+use hyper::Client;
+#[cfg(test)]
+use hyper::header::Host;
+
+#[cfg(test)]
+#[test]
+fn author_feed_test() {
+    let client = Client::new();
+    let (jwt, slug, user_name) = login_create_article(false);
+    let draft_slug = create_unpublished_article(&jwt, r#""status": "draft""#);
+    let url = format!("http://localhost:6767/api/profiles/{}/feed.atom", user_name);
+
+    let mut res = client
+        .get(&url)
+        .header(Host { hostname: "attacker.example".to_string(), port: None })
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    assert!(buffer.contains(&format!("/article/{}</id>", slug)));
+    assert!(buffer.contains("<content type=\"html\">&lt;p&gt;You have to believe&lt;/p&gt;"));
+    assert!(!buffer.contains(&draft_slug));
+    assert!(!buffer.contains("attacker.example"));
+    assert!(buffer.contains(&format!("<link rel=\"self\" href=\"http://localhost:6767/api/profiles/{}/feed.atom\"/>", user_name)));
+
+    let etag = res.headers.get::<ETag>().unwrap().clone();
+    let res = client
+        .get(&url)
+        .header(IfNoneMatch::Items(vec![etag.0]))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotModified);
+
+    let res = client
+        .get("http://localhost:6767/api/profiles/nobody-by-that-name/feed.atom")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+}
+
+#[cfg(test)]
+#[test]
+fn global_feed_test() {
+    let client = Client::new();
+    login_create_article(false);
+
+    let mut res = client.get("http://localhost:6767/api/articles.rss").send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+    assert!(buffer.contains("<rss version=\"2.0\">"));
+    assert!(buffer.contains("<item>"));
+
+    let res = client.get("http://localhost:6767/api/tags/dragons/feed.atom").send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
@@ -0,0 +1,337 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+lazy_static! {
+    static ref RESTORE_WINDOW_HOURS : i64 = get_config()
+        .retention
+        .and_then(|c| c.restore_window_hours)
+        .unwrap_or(72);
+    static ref PURGE_INTERVAL_MINUTES : u64 = get_config()
+        .retention
+        .and_then(|c| c.purge_interval_minutes)
+        .unwrap_or(60);
+}
+
+/// Deleted content may be brought back until the window is over, after that it's up for purging.
+pub fn is_restorable(deleted_at: Option<NaiveDateTime>, now: NaiveDateTime, window_hours: i64) -> bool {
+    deleted_at.map_or(false, |deleted_at| now - deleted_at <= chrono::Duration::hours(window_hours))
+}
+
+#[cfg(feature = "diesel")]
+pub fn soft_delete_article(article_id: i32) {
+    use schema::articles::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(articles.filter(id.eq(article_id)))
+        .set(deletedat.eq(Some(Utc::now().naive_utc())))
+        .execute(&connection)
+        .expect("Failed to delete an article");
+}
+
+#[cfg(feature = "diesel")]
+pub fn soft_delete_comment(comment_id: i32) {
+    use schema::comments::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(comments.filter(id.eq(comment_id)))
+        .set(deletedat.eq(Some(Utc::now().naive_utc())))
+        .execute(&connection)
+        .expect("Failed to delete a comment");
+}
+
+/// Removes a deleted article for good, along with everything hanging off it.
+#[cfg(feature = "diesel")]
+fn purge_article(connection: &PgConnection, article: Article) -> QueryResult<()> {
+    use schema::{articles, comments, favoritedarticles};
+
+    let article_id = article.id;
+    connection.transaction::<_, diesel::result::Error, _>(|| {
+        delete_reports_for(connection, article_id, None)?;
+        delete_notifications_for(connection, article_id, None)?;
+        delete_revisions_for(connection, article_id)?;
+        delete_bookmarks_for(connection, article_id)?;
+        delete_tags_for_article(connection, article_id)?;
+
+        diesel::delete(favoritedarticles::table.filter(favoritedarticles::articleid.eq(article_id)))
+            .execute(connection)?;
+        diesel::delete(comments::table.filter(comments::articleid.eq(article_id)))
+            .execute(connection)?;
+        diesel::delete(articles::table.filter(articles::id.eq(article_id)))
+            .execute(connection)?;
+        Ok(())
+    })
+}
+
+#[cfg(feature = "diesel")]
+fn purge_comment(connection: &PgConnection, comment: Comment) -> QueryResult<()> {
+    use schema::comments::dsl::*;
+
+    connection.transaction::<_, diesel::result::Error, _>(|| {
+        delete_reports_for(connection, comment.articleid, Some(comment.id))?;
+        delete_notifications_for(connection, comment.articleid, Some(comment.id))?;
+        diesel::delete(comments.filter(id.eq(comment.id))).execute(connection)?;
+        Ok(())
+    })
+}
+
+/// Purges whatever was deleted longer ago than the restore window, returns how many
+/// articles and comments went.
+#[cfg(feature = "diesel")]
+pub fn purge_expired(connection: &PgConnection) -> QueryResult<(usize, usize)> {
+    use schema::{articles, comments};
+
+    let cutoff = Utc::now().naive_utc() - chrono::Duration::hours(*RESTORE_WINDOW_HOURS);
+
+    let expired_articles: Vec<Article> = articles::table
+        .filter(articles::deletedat.lt(cutoff))
+        .load(connection)?;
+    let expired_comments: Vec<Comment> = comments::table
+        .filter(comments::deletedat.lt(cutoff))
+        .load(connection)?;
+
+    // comments of purged articles go along with them
+    let purged_article_ids: Vec<i32> = expired_articles.iter().map(|article| article.id).collect();
+    let expired_comments: Vec<Comment> = expired_comments
+        .into_iter()
+        .filter(|comment| !purged_article_ids.contains(&comment.articleid))
+        .collect();
+
+    let counts = (expired_articles.len(), expired_comments.len());
+    for comment in expired_comments {
+        purge_comment(connection, comment)?;
+    }
+    for article in expired_articles {
+        purge_article(connection, article)?;
+    }
+    Ok(counts)
+}
+
+/// Runs `purge_expired` every `purge_interval_minutes` for as long as the server is up.
+/// A round that fails is logged and whatever it left is purged the next time.
+#[cfg(feature = "diesel")]
+pub fn start_purge_thread() {
+    let interval = ::std::time::Duration::from_secs(*PURGE_INTERVAL_MINUTES * 60);
+
+    ::std::thread::spawn(move || loop {
+        if let Some(connection) = try_establish_connection() {
+            match purge_expired(&connection) {
+                Ok((0, 0)) => {}
+                Ok((article_count, comment_count)) => {
+                    println!("purged {} articles and {} comments", article_count, comment_count)
+                }
+                Err(error) => println!("Error purging deleted content: {}", error),
+            }
+        }
+        ::std::thread::sleep(interval);
+    });
+}
+
+#[cfg(feature = "diesel")]
+fn find_deleted_article(url_slug: &str) -> Option<Article> {
+    use schema::articles::dsl::*;
+
+    let connection = establish_connection();
+    articles
+        .filter(slug.eq(url_slug))
+        .filter(deletedat.is_not_null())
+        .first(&connection)
+        .ok()
+}
+
+#[cfg(feature = "diesel")]
+fn restore_article(article: Article) -> Option<ArticleResult> {
+    use schema::articles::dsl::*;
+
+    let connection = establish_connection();
+    diesel::update(articles.filter(id.eq(article.id)))
+        .set(deletedat.eq(None::<NaiveDateTime>))
+        .execute(&connection)
+        .expect("Failed to restore an article");
+
+    get_advanced_article(&article.slug)
+}
+
+pub fn restore_article_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let slug = &caps[0].replace("/api/articles/", "").replace("/restore", "");
+
+    #[cfg(feature = "diesel")]
+    {
+        let now = Utc::now().naive_utc();
+        let article = match find_deleted_article(slug) {
+            Some(ref article) if is_restorable(article.deletedAt, now, *RESTORE_WINDOW_HOURS) => article.clone(),
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::RestoreContent, Some(article.author)) {
+            Some(res) => res,
+            None => return,
+        };
+
+        process(res, restore_article, article);
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn restore_comment(comment_id: i32) -> Option<CommentResult> {
+    use schema::comments::dsl::*;
+
+    let connection = establish_connection();
+    let comment: Comment = diesel::update(comments.filter(id.eq(comment_id)))
+        .set(deletedat.eq(None::<NaiveDateTime>))
+        .get_result(&connection)
+        .expect("Failed to restore a comment");
+
+    Some(CommentResult { comment: comment })
+}
+
+pub fn restore_comment_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let path = caps[0].replace("/api/articles/", "").replace("/restore", "");
+    let mut parts = path.splitn(2, "/comments/");
+    let slug = parts.next().unwrap_or("").to_string();
+    let comment_id = parts.next().and_then(|id| id.parse::<i32>().ok()).unwrap_or(0);
+
+    #[cfg(feature = "diesel")]
+    {
+        use schema::comments;
+
+        // the comment has to be on the article in the url, and that one must still be around
+        let article_id = match find_article(&slug) {
+            Some(article) => article.id,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("article", "not found")),
+        };
+
+        let connection = establish_connection();
+        let now = Utc::now().naive_utc();
+        let comment: Option<Comment> = comments::table
+            .filter(comments::id.eq(comment_id))
+            .filter(comments::articleid.eq(article_id))
+            .first(&connection)
+            .ok();
+        let owner_id = match comment {
+            Some(ref comment) if is_restorable(comment.deletedAt, now, *RESTORE_WINDOW_HOURS) => comment.author,
+            _ => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("comment", "not found")),
+        };
+        let res = match require_permission(res, logged_id, Permission::RestoreContent, Some(owner_id)) {
+            Some(res) => res,
+            None => return,
+        };
+
+        process(res, restore_comment, comment_id);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn restore_window_test() {
+    let now = Utc::now().naive_utc();
+
+    assert!(!is_restorable(None, now, 72));
+    assert!(is_restorable(Some(now - chrono::Duration::hours(71)), now, 72));
+    assert!(!is_restorable(Some(now - chrono::Duration::hours(73)), now, 72));
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn restore_article_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+    let url = format!("http://localhost:6767/api/articles/{}", slug);
+
+    let res = client
+        .delete(&url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+
+    // the slug stays with the deleted article, a new one can't take it over
+    let res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(&format!(r#"{{"article": {{"title": "{}","description": "Again","body": "Again"}}}}"#, slug))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    // only the author or an admin may bring it back
+    let (_, email) = register_jacob();
+    let other_jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let res = client
+        .post(&format!("{}/restore", url))
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let res = client
+        .post(&format!("{}/restore", url))
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn restore_comment_test() {
+    let client = Client::new();
+    let (jwt, slug, _) = login_create_article(false);
+    let (other_jwt, other_slug, _) = login_create_article(false);
+
+    let mut res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments", slug))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(r#"{"comment": {"body": "His name was my name too."}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let comment_result: CommentResult = serde_json::from_str(&buffer).unwrap();
+    let comment_id = comment_result.comment.id;
+
+    let res = client
+        .delete(&format!("http://localhost:6767/api/articles/{}/comments/{}", slug, comment_id))
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // the comment isn't on that other article
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments/{}/restore", other_slug, comment_id))
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+
+    let res = client
+        .post(&format!("http://localhost:6767/api/articles/{}/comments/{}/restore", slug, comment_id))
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
@@ -49,11 +49,14 @@ use jwt::{Header, Registered, Token};
 
 use super::*;
 
-pub fn new_token(user_id: &str, _: &str) -> Option<String> {
+/// Issues a short-lived access token; the user's token version travels in `jti`.
+pub fn new_token(user_id: i32, token_version: i32) -> Option<String> {
     let header: jwt::Header = Default::default();
     let claims = jwt::Registered {
         iss: Some("mikkyang.com".into()),
-        sub: Some(user_id.into()),
+        sub: Some(user_id.to_string()),
+        exp: Some(since_the_epoch() / 1000 + *ACCESS_TOKEN_SECONDS),
+        jti: Some(token_version.to_string()),
         ..Default::default()
     };
     let token = Token::new(header, claims);
@@ -61,24 +64,56 @@ pub fn new_token(user_id: &str, _: &str) -> Option<String> {
     token.signed(b"secret_key", Sha256::new()).ok()
 }
 
+#[cfg(feature = "diesel")]
+fn get_token_version(user_id: i32) -> Option<i32> {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    users
+        .filter(id.eq(user_id))
+        .select(tokenversion)
+        .first(&connection)
+        .ok()
+}
+
 pub fn login(token: &str) -> Option<i32> {
-    let token = Token::<Header, Registered>::parse(token).unwrap();
-
-    if token.verify(b"secret_key", Sha256::new()) {
-        match token.claims.sub {
-            Some(token) => {
-                match token.parse::<i32>() {
-                    Ok(result) => Some(result),
-                    Err(_) => None,
-                }
+    let token = match Token::<Header, Registered>::parse(token) {
+        Ok(token) => token,
+        Err(_) => return None,
+    };
+
+    if !token.verify(b"secret_key", Sha256::new()) {
+        return None;
+    }
+
+    let not_expired = match token.claims.exp {
+        Some(exp) => exp > since_the_epoch() / 1000,
+        None => false,
+    };
+    if !not_expired {
+        return None;
+    }
+
+    let user_id = match token.claims.sub {
+        Some(ref sub) => {
+            match sub.parse::<i32>() {
+                Ok(result) => result,
+                Err(_) => return None,
             }
-            _ => None,
         }
+        _ => return None,
+    };
 
-
-    } else {
-        None
+    // a password change or "log out everywhere" bumps the version and so revokes the token
+    #[cfg(feature = "diesel")]
+    {
+        let token_version = token.claims.jti.as_ref().and_then(|v| v.parse::<i32>().ok());
+        if token_version.is_none() || token_version != get_token_version(user_id) {
+            return None;
+        }
     }
+
+    Some(user_id)
 }
 
 #[cfg(feature = "tiberius")]
@@ -119,6 +154,9 @@ fn get_profile_from_row(row: tiberius::query::QueryRow) -> Option<ProfileResult>
     let result = Some(ProfileResult {
         profile: Profile {
             following: following,
+            followersCount: None,
+            followingCount: None,
+            articlesCount: None,
             bio: bio.map(|s| s.to_string()),
             image: image.map(|s| s.to_string()),
             username: user_name.to_string(),
@@ -144,16 +182,61 @@ pub fn create_user<'a>(new_user: NewUser) -> Option<UserResult> {
         .into(users::table)
         .get_result(&connection)
         .expect("Error saving new user");
+
+    send_verification(&user);
     Some(UserResult { user: user })
 }
 
+#[cfg(feature = "diesel")]
+fn check_unique_user(user_email: &str, user_name: &str, except_user_id: i32) -> ValidationErrors {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    let mut errors = ValidationErrors::new();
+
+    let email_count: i64 = users
+        .filter(email.eq(user_email).and(id.ne(except_user_id)))
+        .count()
+        .get_result(&connection)
+        .unwrap();
+    if email_count > 0 {
+        errors.add("email", "has already been taken");
+    }
+
+    let username_count: i64 = users
+        .filter(username.eq(user_name).and(id.ne(except_user_id)))
+        .count()
+        .get_result(&connection)
+        .unwrap();
+    if username_count > 0 {
+        errors.add("username", "has already been taken");
+    }
+    errors
+}
+
 pub fn registration_handler(req: Request, res: Response, _: Captures) {
     let (body, _) = prepare_parameters(req);
 
-    let registration: Registration = serde_json::from_str(&body).unwrap();
+    let registration: Registration = match serde_json::from_str(&body) {
+        Ok(registration) => registration,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+    };
     let user = registration.user;
+
+    let errors = user.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+    #[cfg(feature = "diesel")]
+    {
+        let errors = check_unique_user(&user.email, &user.username, 0);
+        if !errors.is_empty() {
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+    }
+
     let email: &str = &user.email;
-    let token: &str = &crypto::pbkdf2::pbkdf2_simple(&user.password, 10000).unwrap();
+    let token: &str = &hash_password(&user.password);
     let user_name: &str = &user.username;
 
     #[cfg(feature = "tiberius")]
@@ -186,40 +269,89 @@ pub fn registration_handler(req: Request, res: Response, _: Captures) {
 
 #[cfg(feature = "diesel")] 
 fn update_user(updated: UpdatedUser) -> Option<UserResult> {
+    // an empty changeset is an error in diesel, there is simply nothing to save
+    if updated.email.is_none() && updated.token.is_none() && updated.username.is_none() &&
+        updated.image.is_none() && updated.bio.is_none()
+    {
+        return get_user_by_id(updated.id);
+    }
+
     let conn = establish_connection();
 
     let result = updated.save_changes::<User>(&conn).unwrap();
 
+    // a new address has to be confirmed again before the restrictions are lifted
+    if updated.email.is_some() {
+        send_verification(&result);
+    }
+
     Some(UserResult { user:result })
 }
 
 pub fn update_user_handler(req: Request, res: Response, _: Captures) {
     let (body, logged_in_user_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_in_user_id) {
+        Some(res) => res,
+        None => return,
+    };
 
-    let updated_user: UpdateUser = serde_json::from_str(&body).unwrap();
-    let original_user : User = get_user_by_id(logged_in_user_id).unwrap().user;
-    let original_bio = original_user.bio.unwrap_or_default();
-    let original_image = original_user.image.unwrap_or_default();
-
-    let user_name: &str = &updated_user.user.username.as_ref().map(|x| &**x).unwrap_or(
-        &original_user.username,
-    );
-    let new_bio: &str = updated_user.user.bio.as_ref().map(|x| &**x).unwrap_or(&original_bio);
-    let new_image: &str = updated_user.user.image.as_ref().map(|x| &**x).unwrap_or(&original_image);
-    let new_email: &str = &updated_user.user.email.as_ref().map(|x| &**x).unwrap_or(&original_user.email);
-    let new_password: &str = &updated_user.user.password.as_ref().map(|x| &**x).unwrap_or("");
+    let updated_user: UpdateUser = match serde_json::from_str(&body) {
+        Ok(updated_user) => updated_user,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+    };
 
-    let new_token: &str = &crypto::pbkdf2::pbkdf2_simple(new_password, 10000).unwrap();
-         
+    let errors = updated_user.user.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
 
     #[cfg(feature = "diesel")] {
+        let original_user : User = get_user_by_id(logged_in_user_id).unwrap().user;
+        let changes = updated_user.user;
+
+        let new_email: Option<&str> = match changes.email {
+            Some(ref changed) if *changed != original_user.email => Some(changed),
+            _ => None,
+        };
+        let user_name: Option<&str> = changes.username.as_ref().map(|x| &**x);
+        let new_bio: Option<Option<&str>> = changes.bio.as_ref().map(|x| x.as_ref().map(|x| &**x));
+        let new_image: Option<Option<&str>> = changes.image.as_ref().map(|x| x.as_ref().map(|x| &**x));
+
+        if new_email.is_some() || changes.password.is_some() {
+            let current_password: &str = changes.currentPassword.as_ref().map(|x| &**x).unwrap_or("");
+            if current_password.is_empty() {
+                let errors = ValidationErrors::with("currentPassword", "can't be blank");
+                return send_errors(res, StatusCode::UnprocessableEntity, errors);
+            }
+            if !verify_password(current_password, &original_user.token) {
+                let errors = ValidationErrors::with("currentPassword", "is invalid");
+                return send_errors(res, StatusCode::UnprocessableEntity, errors);
+            }
+        }
+
+        let errors = check_unique_user(
+            new_email.unwrap_or(&original_user.email),
+            user_name.unwrap_or(&original_user.username),
+            logged_in_user_id,
+        );
+        if !errors.is_empty() {
+            return send_errors(res, StatusCode::UnprocessableEntity, errors);
+        }
+
+        let new_token: Option<String> = changes.password.as_ref().map(|x| hash_password(x));
+        if new_token.is_some() {
+            revoke_all_sessions(logged_in_user_id);
+        }
+
         let updated = UpdatedUser  {
             id : logged_in_user_id,
             email : new_email,
             bio : new_bio,
             image : new_image,
-            token : new_token,
+            token : new_token.as_ref().map(|x| &**x),
             username : user_name,
+            tokenversion : None,
+            verified : new_email.map(|_| false),
         };
 
         process(res, update_user, updated)
@@ -251,16 +383,14 @@ pub fn update_user_handler(req: Request, res: Response, _: Captures) {
 }
 
 #[cfg(feature = "diesel")]
-fn get_user_by_name(user_name: &str) -> Option<User> {
+pub fn get_user_by_name(user_name: &str) -> Option<User> {
     use schema::users::dsl::*;
 
     let connection = establish_connection();
-    let result: User = 
-        users
+    users
         .filter(username.eq(user_name))
         .first(&connection)
-        .unwrap();
-    Some(result)
+        .ok()
 }
 
 #[cfg(feature = "diesel")]
@@ -277,6 +407,10 @@ pub fn get_user_by_id(user_id: i32) -> Option<UserResult> {
 
 pub fn get_current_user_handler(req: Request, res: Response, _: Captures) {
     let (_, logged_in_user_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_in_user_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     #[cfg(feature = "tiberius")]
     process(
@@ -290,29 +424,28 @@ pub fn get_current_user_handler(req: Request, res: Response, _: Captures) {
     process(res, get_user_by_id, logged_in_user_id);
 }
 
-fn get_profile_result(user: User) -> Option<ProfileResult> {
-    let followed = is_followed(user.id);
-    let result = Profile {
-        username : user.username,
-        bio : user.bio,
-        image : user.image,
-        following : followed,
-    };
+/// The profile of a user as seen by the viewer, `following` is whether the viewer follows them.
+#[cfg(feature = "diesel")]
+pub fn get_profile_result(params: (User, i32)) -> Option<ProfileResult> {
+    let (user, viewer_id) = params;
 
-     Some(ProfileResult { profile: result,})
+     Some(ProfileResult { profile: profile_for(user, viewer_id),})
 }
 
 pub fn get_profile_handler(req: Request, res: Response, c: Captures) {
-    let (_, _) = prepare_parameters(req);
+    let (_, logged_in_user_id) = prepare_parameters(req);
 
     let caps = c.unwrap();
     let profile = &caps[0].replace("/api/profiles/", "");
     println!("profile: {}", profile);
 
     #[cfg(feature = "diesel")] {
-        let user = get_user_by_name(profile).unwrap();
+        let user = match get_user_by_name(profile) {
+            Some(user) => user,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found")),
+        };
 
-        process(res, get_profile_result, user)
+        process(res, get_profile_result, (user, logged_in_user_id))
     }
 
     #[cfg(feature = "tiberius")]
@@ -327,54 +460,48 @@ pub fn get_profile_handler(req: Request, res: Response, c: Captures) {
 
 pub fn unfollow_handler(req: Request, res: Response, c: Captures) {
     let (_, logged_in_user_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_in_user_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     let caps = c.unwrap();
     let profile = &caps[0].replace("/api/profiles/", "").replace("/follow", "");
     println!("profile: {}", profile);
 
     #[cfg(feature = "diesel")] {
-        let following : User = get_user_by_name(profile).unwrap();
+        let following : User = match get_user_by_name(profile) {
+            Some(user) => user,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found")),
+        };
 
         unfollow_user(logged_in_user_id, following.id);
 
-        let updated_user = get_user_by_name(profile).unwrap();
-
-        process(res, get_profile_result, updated_user)
+        process(res, get_profile_result, (following, logged_in_user_id))
     }
 
     #[cfg(feature = "tiberius")]
     process(
         res,
-        r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;DELETE TOP (1) from [dbo].[Followings] WHERE [FollowerId] = @P2;"#, PROFILE_SELECT,
+        r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;DELETE from [dbo].[Followings] WHERE [FollowerId] = @P2 AND [FollowingId] = (SELECT TOP (1) [Id] FROM [Users] where UserName = @P1);"#, PROFILE_SELECT,
         get_profile_from_row,
         &[&(profile.as_str()), &logged_in_user_id]
     );
 }
 
 #[cfg(feature = "diesel")]
-fn is_followed(user_id: i32) -> bool {
-    use schema::followings::dsl::*;
-
-    let connection = establish_connection();
-
-    let followers_count: i64 = followings
-        .filter(followingid.eq(user_id))
-        .count()
-        .get_result(&connection)
-        .unwrap();
-    followers_count > 0    
-}
-
-#[cfg(feature = "diesel")]
-fn follow_user<'a>(follow: NewFollowing) {  
+fn follow_user<'a>(follow: NewFollowing) -> bool {  
     let connection = establish_connection();
 
+    use diesel::pg::upsert::*;
     use schema::followings;
 
-    let _relationship: Following = diesel::insert(&follow)
+    // following twice is the same as following once, true only the first time
+    let inserted = diesel::insert(&follow.on_conflict_do_nothing())
     .into(followings::table)
-    .get_result(&connection)
-    .expect("Error saving new favorited article relationship");    
+    .execute(&connection)
+    .expect("Error saving new following relationship");    
+    inserted > 0
 }
 
 #[cfg(feature = "diesel")]
@@ -383,94 +510,142 @@ fn unfollow_user<'a>(follower_id: i32, following_id: i32) {
 
     use schema::followings::dsl::*;
 
-    let relationship: Following = followings
-        .filter(followerid.eq(follower_id).and(followingid.eq(following_id)))
-        .first(&connection)
-        .unwrap();
-
-    diesel::delete(followings.filter(id.eq(relationship.id))).execute(&connection).expect("Failed to unfollow user");   
+    diesel::delete(followings.filter(followerid.eq(follower_id).and(followingid.eq(following_id))))
+        .execute(&connection)
+        .expect("Failed to unfollow user");   
 }
 
 pub fn follow_handler(req: Request, res: Response, c: Captures) {
 
     let (_, logged_in_user_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_in_user_id) {
+        Some(res) => res,
+        None => return,
+    };
 
     let caps = c.unwrap();
     let profile = &caps[0].replace("/api/profiles/", "").replace("/follow", "");
     println!("profile: {}", profile);
 
     #[cfg(feature = "diesel")] {
-        let followed_user : User = get_user_by_name(profile).unwrap();
+        let followed_user : User = match get_user_by_name(profile) {
+            Some(user) => user,
+            None => return send_errors(res, StatusCode::NotFound, ValidationErrors::with("username", "not found")),
+        };
+
+        if is_blocked(followed_user.id, logged_in_user_id) {
+            return send_errors(res, StatusCode::Forbidden, ValidationErrors::with("username", "can't be followed"));
+        }
 
         let follow = NewFollowing {
             followerid : logged_in_user_id,
             followingid : followed_user.id,
         };
 
-        follow_user(follow);
+        if follow_user(follow) {
+            notify(followed_user.id, logged_in_user_id, NotificationKind::Follow, None, None);
+            dispatch_followed(logged_in_user_id, &followed_user);
+        }
 
-        let updated = get_user_by_name(profile).unwrap();
-        process(res, get_profile_result, updated)
+        process(res, get_profile_result, (followed_user, logged_in_user_id))
     }
 
     #[cfg(feature = "tiberius")]
     process(
         res,
         r#"DECLARE @username nvarchar(max) = @P1;DECLARE @logged int = @P2;INSERT INTO [dbo].[Followings] ([FollowingId] ,[FollowerId])
-     SELECT @P2,(SELECT TOP (1) [Id]  FROM [Users] where UserName = @P1) EXCEPT SELECT [FollowingId] ,[FollowerId] from Followings;"#, PROFILE_SELECT,
+     SELECT (SELECT TOP (1) [Id]  FROM [Users] where UserName = @P1),@P2 EXCEPT SELECT [FollowingId] ,[FollowerId] from Followings;"#, PROFILE_SELECT,
         get_profile_from_row,
         &[&(profile.as_str()), &logged_in_user_id]
     );
 }
 
-pub fn authentication_handler(mut req: Request, mut res: Response, _: Captures) {
+pub fn authentication_handler(mut req: Request, res: Response, _: Captures) {
+    let address = client_address(&req);
     let mut body = String::new();
     let _ = req.read_to_string(&mut body);
-    let login: Login = serde_json::from_str(&body).unwrap();
+    let login: Login = match serde_json::from_str(&body) {
+        Ok(login) => login,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("user", "can't be read")),
+    };
+
+    let errors = login.user.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+
     let user_email: &str = &login.user.email;
+    let mut res = match require_not_throttled(res, user_email, address) {
+        Some(res) => res,
+        None => return,
+    };
 
+    #[cfg(feature = "tiberius")]
     let mut result: Option<UserResult> = None;
     #[cfg(feature = "diesel")]
     {
         use schema::users::dsl::*;
 
         let connection = establish_connection();
-        let user: User = users
-            .filter(email.eq(user_email))
-            .first(&connection)
-            .unwrap();
-        let stored_hash: &str = &user.token.to_owned();
-        let user_id = user.id;
-        let authenticated_user = crypto::pbkdf2::pbkdf2_check(&login.user.password, &stored_hash);
-        result = Some(UserResult { user: user });
-
-        match authenticated_user {
-            Ok(valid) => {
-                if valid {
-                    let token2 = new_token(user_id.to_string().as_ref(), &login.user.password)
-                        .unwrap();
-
-                    res.headers_mut().set(Authorization(
-                        Bearer { token: token2.to_owned() },
-                    ));
-                    res.headers_mut().set(AccessControlAllowOrigin::Any);
-                    res.headers_mut().set(AccessControlAllowHeaders(vec![
-                        UniCase("content-type".to_owned()),
-                        UniCase("authorization".to_owned()),
-                    ]));
-                    res.headers_mut().set(ContentType(Mime(
-                        TopLevel::Application,
-                        SubLevel::Json,
-                        vec![(Attr::Charset, Value::Utf8)],
-                    )));
+        let user: Option<User> = users.filter(email.eq(user_email)).first(&connection).ok();
+        let authenticated_user = verify_password_or_dummy(
+            &login.user.password,
+            user.as_ref().map(|user| user.token.as_str()),
+        );
 
-                    *res.status_mut() = StatusCode::Ok;
-                }
-            }
+        // unknown emails and wrong passwords look exactly the same from outside
+        let user = match (user, authenticated_user) {
+            (Some(user), true) => user,
             _ => {
-                result = None;
+                record_failed_login(user_email, address);
+                let errors = ValidationErrors::with("email or password", "is invalid");
+                return send_errors(res, StatusCode::Unauthorized, errors);
             }
+        };
+        record_successful_login(user_email);
+
+        if user.suspendedAt.is_some() {
+            return send_errors(res, StatusCode::Forbidden, ValidationErrors::with("user", "is suspended"));
+        }
+
+        let stored_hash: &str = &user.token.to_owned();
+        let user_id = user.id;
+        let token_version = user.tokenversion;
+
+        // upgrade hashes made under an older policy while we know the password
+        if needs_rehash(stored_hash) {
+            diesel::update(users.filter(id.eq(user_id)))
+                .set(token.eq(hash_password(&login.user.password)))
+                .execute(&connection)
+                .expect("Error upgrading password hash");
         }
+
+        let token2 = new_token(user_id, token_version).unwrap();
+
+        res.headers_mut().set(Authorization(
+            Bearer { token: token2.to_owned() },
+        ));
+        res.headers_mut().set(AccessControlAllowOrigin::Any);
+        res.headers_mut().set(AccessControlAllowHeaders(vec![
+            UniCase("content-type".to_owned()),
+            UniCase("authorization".to_owned()),
+        ]));
+        res.headers_mut().set(ContentType(Mime(
+            TopLevel::Application,
+            SubLevel::Json,
+            vec![(Attr::Charset, Value::Utf8)],
+        )));
+
+        *res.status_mut() = StatusCode::Ok;
+
+        let refresh_token = create_session(user_id);
+        return send_result(
+            res,
+            LoginResult {
+                user: user,
+                refreshToken: refresh_token,
+            },
+        );
     }
     #[cfg(feature = "tiberius")]
     {
@@ -479,44 +654,42 @@ pub fn authentication_handler(mut req: Request, mut res: Response, _: Captures)
             .and_then(|conn| conn.query( "SELECT TOP 1 [Email],[Token],[UserName],[Bio],[Image], Id FROM [dbo].[Users] WHERE [Email] = @P1", &[&user_email] )
             .for_each_row(|row| {
                 let (user_id,stored_hash,result2) = get_user_from_row(row);
-                let authenticated_user = crypto::pbkdf2::pbkdf2_check( &login.user.password, &stored_hash);
+                let authenticated_user = verify_password( &login.user.password, &stored_hash);
                 *res.status_mut() = StatusCode::Unauthorized;
 
-                match authenticated_user {
-                    Ok(valid) => {
-                        if valid {                     
-                            let token = new_token(user_id.to_string().as_ref(), &login.user.password).unwrap();
-
-                            res.headers_mut().set(
-                                Authorization(
-                                    Bearer {
-                                        token: token.to_owned()
-                                    }
-                                )
-                            );
-                            res.headers_mut().set(
-                                AccessControlAllowOrigin::Any
-                            );
-                            res.headers_mut().set(
-                                AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
-                            );                            
-                            res.headers_mut().set(
-                                ContentType(Mime(TopLevel::Application, SubLevel::Json,
-                                            vec![(Attr::Charset, Value::Utf8)]))
-                            );  
-
-                            *res.status_mut() = StatusCode::Ok;
-                            result = result2;
-                        }
-                    }
-                    _ => { result = None; }
-                }            
+                if authenticated_user {
+                    let token = new_token(user_id, 0).unwrap();
+
+                    res.headers_mut().set(
+                        Authorization(
+                            Bearer {
+                                token: token.to_owned()
+                            }
+                        )
+                    );
+                    res.headers_mut().set(
+                        AccessControlAllowOrigin::Any
+                    );
+                    res.headers_mut().set(
+                        AccessControlAllowHeaders(vec![UniCase("content-type".to_owned()), UniCase("authorization".to_owned())])
+                    );                            
+                    res.headers_mut().set(
+                        ContentType(Mime(TopLevel::Application, SubLevel::Json,
+                                    vec![(Attr::Charset, Value::Utf8)]))
+                    );  
+
+                    *res.status_mut() = StatusCode::Ok;
+                    result = result2;
+                } else {
+                    result = None;
+                }
                 Ok(())
             })
         );
         sql.run(get_user_cmd).unwrap();
     }
 
+    #[cfg(feature = "tiberius")]
     if result.is_some() {
         let result = result.unwrap();
         let result = serde_json::to_string(&result).unwrap();
@@ -536,6 +709,13 @@ pub static JACOB_PASSWORD: &'static str = r#"jakejake"#;
 
 #[cfg(test)]
 pub fn register_jacob() -> (std::string::String, std::string::String) {
+    let (user_name, email) = register_unverified_jacob();
+    verify_jacob(&email);
+    (user_name, email)
+}
+
+#[cfg(test)]
+pub fn register_unverified_jacob() -> (std::string::String, std::string::String) {
     let client = Client::new();
     let since = since_the_epoch();
 
@@ -562,11 +742,30 @@ pub fn register_jacob() -> (std::string::String, std::string::String) {
     let registered_user = registration.user;
     assert_eq!(registered_user.email, email);
     assert_eq!(registered_user.username, user_name);
+    assert_eq!(registered_user.verified, false);
 
     assert_eq!(res.status, hyper::Ok);
     (user_name, email)
 }
 
+#[cfg(test)]
+pub fn verify_jacob(email: &str) {
+    let client = Client::new();
+
+    let verification_token = read_token_from_mail(email, "/verify?token=");
+    let url = format!("http://localhost:6767/api/users/verify?token={}", verification_token);
+
+    let mut res = client.get(&url).send().unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let verification: UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(verification.user.email, email);
+    assert_eq!(verification.user.verified, true);
+    assert!(!buffer.contains("\"token\""));
+}
+
 #[cfg(test)]
 pub fn login_jacob(email: std::string::String, password: String) -> std::string::String {
     let client = Client::new();
@@ -616,6 +815,7 @@ pub fn follow_jacob() -> (std::string::String, std::string::String, std::string:
     let profile = profile_result.profile;
     assert_eq!(profile.username, user_name);
     assert_eq!(profile.following, true);
+    assert_eq!(profile.followersCount, Some(1));
 
     assert_eq!(res.status, hyper::Ok);
 
@@ -628,6 +828,66 @@ fn registration_test() {
     register_jacob();
 }
 
+#[cfg(test)]
+#[test]
+fn registration_validation_test() {
+    let client = Client::new();
+    let (user_name, email) = register_jacob();
+
+    let body = format!(
+        r#"{{"user":{{"username": "{}","email": "{}","password": ""}}}}"#,
+        user_name,
+        email
+    );
+
+    let mut res = client
+        .post("http://localhost:6767/api/users")
+        .body(&body)
+        .send()
+        .unwrap();
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.errors["password"], vec!["can't be blank".to_string()]);
+
+    let body = format!(
+        r#"{{"user":{{"username": "{}","email": "{}","password": "{}"}}}}"#,
+        user_name,
+        email,
+        JACOB_PASSWORD
+    );
+
+    let mut res = client
+        .post("http://localhost:6767/api/users")
+        .body(&body)
+        .send()
+        .unwrap();
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.errors["email"], vec!["has already been taken".to_string()]);
+    assert_eq!(result.errors["username"], vec!["has already been taken".to_string()]);
+
+    let mut res = client
+        .post("http://localhost:6767/api/users")
+        .body(r#"{"user":{"username": "#)
+        .send()
+        .unwrap();
+
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.errors["user"], vec!["can't be read".to_string()]);
+}
+
 #[cfg(test)]
 //#[test]
 fn login_test() {
@@ -635,6 +895,39 @@ fn login_test() {
     login_jacob(email, JACOB_PASSWORD.to_string());
 }
 
+#[cfg(test)]
+#[test]
+fn invalid_login_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+
+    let attempts = vec![
+        (email, "wrong password".to_string()),
+        (format!("nobody-{}@jake.jake", since_the_epoch()), JACOB_PASSWORD.to_string()),
+    ];
+    let mut answers = Vec::new();
+    for (email, password) in attempts {
+        let body = format!(
+            r#"{{"user":{{"email": "{}","password": "{}"}}}}"#,
+            email,
+            password
+        );
+        let mut res = client
+            .post("http://localhost:6767/api/users/login")
+            .body(&body)
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::status::StatusCode::Unauthorized);
+        answers.push(buffer);
+    }
+
+    assert_eq!(answers[0], answers[1]);
+    let result: ValidationErrors = serde_json::from_str(&answers[0]).unwrap();
+    assert_eq!(result.errors["email or password"], vec!["is invalid".to_string()]);
+}
+
 #[cfg(test)]
 #[test]
 fn get_current_user_test() {
@@ -691,6 +984,58 @@ fn update_user_test() {
     assert_eq!(res.status, hyper::Ok);
 }
 
+#[cfg(test)]
+#[test]
+fn update_user_partial_test() {
+    let client = Client::new();
+    let (user_name, email) = register_jacob();
+    let jwt = login_jacob(email.to_owned(), JACOB_PASSWORD.to_string());
+
+    let url = format!("http://localhost:6767/api/user");
+
+    let mut res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(r#"{"user": { "bio":"I like to skateboard"}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result: UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.user.username, user_name);
+    assert_eq!(result.user.bio, Some("I like to skateboard".to_string()));
+
+    // the password must survive a bio-only update
+    login_jacob(email.to_owned(), JACOB_PASSWORD.to_string());
+
+    let mut res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(r#"{"user": { "bio":null}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+
+    let result: UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.user.bio, None);
+
+    let mut res = client
+        .put(&url)
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"user": { "password":"dragondragon"}}"#)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.errors["currentPassword"], vec!["can't be blank".to_string()]);
+}
+
 #[cfg(test)]
 #[test]
 #[should_panic]
@@ -718,7 +1063,7 @@ fn login_fail_test() {
 }
 
 #[cfg(test)]
-//#[test]
+#[test]
 fn profile_unlogged_test() {
     let client = Client::new();
     let (user_name, _) = register_jacob();
@@ -744,7 +1089,7 @@ fn follow_test() {
 
 
 #[cfg(test)]
-//#[test]
+#[test]
 fn profile_logged_test() {
     let client = Client::new();
 
@@ -768,6 +1113,36 @@ fn profile_logged_test() {
     assert_eq!(res.status, hyper::Ok);
 }
 
+#[cfg(test)]
+#[test]
+fn profile_following_per_viewer_test() {
+    let client = Client::new();
+
+    let (user_name, _, _) = follow_jacob();
+    let (_, email) = register_jacob();
+    let other_jwt = login_jacob(email, JACOB_PASSWORD.to_string());
+    let url = format!("http://localhost:6767/api/profiles/{}", user_name);
+
+    // followed by someone, but not by this viewer
+    let mut res = client
+        .get(&url)
+        .header(Authorization(Bearer { token: other_jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+
+    let profile_result: ProfileResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(profile_result.profile.following, false);
+    assert_eq!(profile_result.profile.followersCount, Some(1));
+
+    let res = client
+        .get("http://localhost:6767/api/profiles/nobody-by-that-name")
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::NotFound);
+}
+
 #[cfg(test)]
 #[test]
 fn unfollow_test() {
@@ -792,3 +1167,38 @@ fn unfollow_test() {
 
     assert_eq!(res.status, hyper::Ok);
 }
+
+#[cfg(test)]
+#[test]
+fn repeat_follow_test() {
+    let client = Client::new();
+
+    let (user_name, _, jwt) = follow_jacob();
+    let url = format!("http://localhost:6767/api/profiles/{}/follow", user_name);
+
+    let mut res = client
+        .post(&url)
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let profile_result: ProfileResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(profile_result.profile.following, true);
+
+    for _ in 0..2 {
+        let mut res = client
+            .delete(&url)
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::Ok);
+
+        let profile_result: ProfileResult = serde_json::from_str(&buffer).unwrap();
+        assert_eq!(profile_result.profile.following, false);
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::BTreeMap;
+
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct ValidationErrors {
+    pub errors: BTreeMap<String, Vec<String>>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> ValidationErrors {
+        ValidationErrors { errors: BTreeMap::new() }
+    }
+
+    pub fn with(field: &str, message: &str) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        errors.add(field, message);
+        errors
+    }
+
+    pub fn add(&mut self, field: &str, message: &str) {
+        self.errors
+            .entry(field.to_string())
+            .or_insert_with(Vec::new)
+            .push(message.to_string());
+    }
+
+    pub fn merge(&mut self, other: ValidationErrors) {
+        for (field, messages) in other.errors {
+            for message in messages {
+                self.add(&field, &message);
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+/// A single check on a named field of an incoming payload.
+pub enum Rule<'a> {
+    Required(&'static str, &'a str),
+    Length(&'static str, &'a str, usize, usize),
+    Email(&'static str, &'a str),
+    Username(&'static str, &'a str),
+    Password(&'static str, &'a str),
+    OneOf(&'static str, &'a str, &'static [&'static str]),
+    Url(&'static str, &'a str),
+}
+
+impl<'a> Rule<'a> {
+    fn check(&self, errors: &mut ValidationErrors) {
+        match *self {
+            Rule::Required(field, value) => {
+                if value.trim().is_empty() {
+                    errors.add(field, "can't be blank");
+                }
+            }
+            // blank values are reported by `Required`, the rest only check what was sent
+            Rule::Length(field, value, min, max) => {
+                let length = value.chars().count();
+                if length > 0 && length < min {
+                    errors.add(field, &format!("is too short (minimum is {} characters)", min));
+                } else if length > max {
+                    errors.add(field, &format!("is too long (maximum is {} characters)", max));
+                }
+            }
+            Rule::Email(field, value) => {
+                if !value.is_empty() && !is_valid_email(value) {
+                    errors.add(field, "is invalid");
+                }
+            }
+            Rule::Username(field, value) => {
+                if !value.is_empty() && !is_valid_username(value) {
+                    errors.add(field, "can only contain letters, numbers, '-' and '_'");
+                }
+            }
+            Rule::Password(field, value) => {
+                if !value.is_empty() {
+                    if let Some(message) = check_password_strength(value) {
+                        errors.add(field, message);
+                    }
+                }
+            }
+            Rule::OneOf(field, value, allowed) => {
+                if !value.is_empty() && !allowed.contains(&value) {
+                    errors.add(field, &format!("must be one of {}", allowed.join(", ")));
+                }
+            }
+            Rule::Url(field, value) => {
+                if !value.is_empty() && !is_valid_url(value) {
+                    errors.add(field, "must be an http or https address");
+                }
+            }
+        }
+    }
+}
+
+pub trait Validate {
+    fn rules(&self) -> Vec<Rule>;
+
+    fn validate(&self) -> ValidationErrors {
+        let mut errors = ValidationErrors::new();
+        for rule in self.rules() {
+            rule.check(&mut errors);
+        }
+        errors
+    }
+}
+
+fn is_valid_email(value: &str) -> bool {
+    let parts: Vec<&str> = value.split('@').collect();
+    if parts.len() != 2 || value.chars().any(|c| c.is_whitespace()) {
+        return false;
+    }
+    let (local, domain) = (parts[0], parts[1]);
+    !local.is_empty() && domain.contains('.') && !domain.starts_with('.') && !domain.ends_with('.')
+}
+
+fn is_valid_username(value: &str) -> bool {
+    value.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+}
+
+fn is_valid_url(value: &str) -> bool {
+    let rest = if value.starts_with("https://") {
+        &value[8..]
+    } else if value.starts_with("http://") {
+        &value[7..]
+    } else {
+        return false;
+    };
+    !rest.is_empty() && !rest.starts_with('/') && !value.chars().any(|c| c.is_whitespace())
+}
+
+// Column sizes from the Users, Articles and Reports tables.
+pub static EMAIL_MAX_LENGTH: usize = 50;
+pub static USERNAME_MAX_LENGTH: usize = 150;
+pub static TITLE_MAX_LENGTH: usize = 250;
+pub static DESCRIPTION_MAX_LENGTH: usize = 250;
+pub static REASON_MAX_LENGTH: usize = 500;
+pub static URL_MAX_LENGTH: usize = 500;
+
+impl Validate for RegistrationDetails {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("email", &self.email),
+            Rule::Length("email", &self.email, 3, EMAIL_MAX_LENGTH),
+            Rule::Email("email", &self.email),
+            Rule::Required("username", &self.username),
+            Rule::Length("username", &self.username, 1, USERNAME_MAX_LENGTH),
+            Rule::Username("username", &self.username),
+            Rule::Required("password", &self.password),
+            Rule::Length("password", &self.password, PASSWORD_POLICY.min_length, PASSWORD_MAX_LENGTH),
+            Rule::Password("password", &self.password),
+        ]
+    }
+}
+
+impl Validate for LoginDetails {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("email", &self.email),
+            Rule::Required("password", &self.password),
+        ]
+    }
+}
+
+impl Validate for PasswordResetDetails {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("email", &self.email),
+            Rule::Email("email", &self.email),
+        ]
+    }
+}
+
+impl Validate for PasswordResetConfirmDetails {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("token", &self.token),
+            Rule::Required("password", &self.password),
+            Rule::Length("password", &self.password, PASSWORD_POLICY.min_length, PASSWORD_MAX_LENGTH),
+            Rule::Password("password", &self.password),
+        ]
+    }
+}
+
+impl Validate for UpdateUserDetail {
+    fn rules(&self) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        if let Some(ref email) = self.email {
+            rules.push(Rule::Required("email", email));
+            rules.push(Rule::Length("email", email, 3, EMAIL_MAX_LENGTH));
+            rules.push(Rule::Email("email", email));
+        }
+        if let Some(ref username) = self.username {
+            rules.push(Rule::Required("username", username));
+            rules.push(Rule::Length("username", username, 1, USERNAME_MAX_LENGTH));
+            rules.push(Rule::Username("username", username));
+        }
+        if let Some(ref password) = self.password {
+            rules.push(Rule::Required("password", password));
+            rules.push(Rule::Length("password", password, PASSWORD_POLICY.min_length, PASSWORD_MAX_LENGTH));
+            rules.push(Rule::Password("password", password));
+        }
+        rules
+    }
+}
+
+impl Validate for ChangeRoleDetails {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("role", &self.role),
+            Rule::OneOf("role", &self.role, ROLE_NAMES),
+        ]
+    }
+}
+
+impl Validate for AddReportDetail {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("reason", &self.reason),
+            Rule::Length("reason", &self.reason, 1, REASON_MAX_LENGTH),
+        ]
+    }
+}
+
+impl Validate for CreateArticleDetail {
+    fn rules(&self) -> Vec<Rule> {
+        vec![
+            Rule::Required("title", &self.title),
+            Rule::Length("title", &self.title, 1, TITLE_MAX_LENGTH),
+            Rule::Length("description", &self.description, 1, DESCRIPTION_MAX_LENGTH),
+            Rule::Required("body", &self.body),
+            Rule::OneOf("status", &self.status, ARTICLE_STATUSES),
+        ]
+    }
+}
+
+impl Validate for UpdateArticleDetail {
+    fn rules(&self) -> Vec<Rule> {
+        let mut rules = Vec::new();
+        if let Some(ref title) = self.title {
+            rules.push(Rule::Required("title", title));
+            rules.push(Rule::Length("title", title, 1, TITLE_MAX_LENGTH));
+        }
+        if let Some(ref description) = self.description {
+            rules.push(Rule::Length("description", description, 1, DESCRIPTION_MAX_LENGTH));
+        }
+        if let Some(ref body) = self.body {
+            rules.push(Rule::Required("body", body));
+        }
+        rules
+    }
+}
+
+impl Validate for AddCommentDetail {
+    fn rules(&self) -> Vec<Rule> {
+        vec![Rule::Required("body", &self.body)]
+    }
+}
+
+impl Validate for AddWebhookDetail {
+    fn rules(&self) -> Vec<Rule> {
+        let mut rules = vec![
+            Rule::Required("url", &self.url),
+            Rule::Length("url", &self.url, 1, URL_MAX_LENGTH),
+            Rule::Url("url", &self.url),
+            Rule::Required("events", self.events.first().map_or("", |event| event)),
+        ];
+        for event in &self.events {
+            rules.push(Rule::OneOf("events", event, WEBHOOK_EVENTS));
+        }
+        rules
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn registration_rules_test() {
+    let registration = RegistrationDetails {
+        email: "not an email".to_string(),
+        username: "".to_string(),
+        password: "".to_string(),
+    };
+
+    let errors = registration.validate().errors;
+    assert_eq!(errors["email"], vec!["is invalid".to_string()]);
+    assert_eq!(errors["username"], vec!["can't be blank".to_string()]);
+    assert_eq!(errors["password"], vec!["can't be blank".to_string()]);
+}
+
+#[cfg(test)]
+#[test]
+fn email_and_username_format_test() {
+    assert!(is_valid_email("jake@jake.jake"));
+    assert!(!is_valid_email("jake@jake"));
+    assert!(!is_valid_email("@jake.jake"));
+    assert!(!is_valid_email("jake@@jake.jake"));
+    assert!(is_valid_username("Jacob-1514929891028-928_CH"));
+    assert!(!is_valid_username("Jacob Jacob"));
+    assert!(!is_valid_username("jacob/../admin"));
+}
+
+#[cfg(test)]
+#[test]
+fn webhook_rules_test() {
+    assert!(is_valid_url("https://chat.example.com/hooks/conduit"));
+    assert!(is_valid_url("http://hooks.example.com:8080"));
+    assert!(!is_valid_url("ftp://example.com"));
+    assert!(!is_valid_url("https:///example.com"));
+    assert!(!is_valid_url("http://exa mple.com"));
+
+    let webhook = AddWebhookDetail {
+        url: "javascript:alert(1)".to_string(),
+        events: vec!["article.created".to_string(), "article.liked".to_string()],
+        global: false,
+    };
+    let errors = webhook.validate().errors;
+    assert_eq!(errors["url"], vec!["must be an http or https address".to_string()]);
+    assert_eq!(errors["events"].len(), 1);
+}
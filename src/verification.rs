@@ -0,0 +1,263 @@
+use hyper::server::{Request, Response};
+use reroute::Captures;
+
+use super::*;
+
+/// Things an account may be kept from doing until its email address is verified.
+pub enum Restricted {
+    Articles,
+    Comments,
+}
+
+fn is_allowed_unverified(action: &Restricted) -> bool {
+    let config = get_config().verification;
+    match *action {
+        Restricted::Articles => config.and_then(|c| c.allow_unverified_articles).unwrap_or(false),
+        Restricted::Comments => config.and_then(|c| c.allow_unverified_comments).unwrap_or(false),
+    }
+}
+
+lazy_static! {
+    static ref ALLOW_UNVERIFIED_ARTICLES : bool = is_allowed_unverified(&Restricted::Articles);
+    static ref ALLOW_UNVERIFIED_COMMENTS : bool = is_allowed_unverified(&Restricted::Comments);
+    static ref VERIFICATION_TOKEN_HOURS : i64 = get_config()
+        .verification
+        .and_then(|c| c.token_hours)
+        .unwrap_or(48);
+}
+
+#[cfg(feature = "diesel")]
+fn is_verified(user_id: i32) -> bool {
+    use schema::users::dsl::*;
+
+    let connection = establish_connection();
+    users
+        .filter(id.eq(user_id))
+        .select(verified)
+        .first(&connection)
+        .unwrap_or(false)
+}
+
+/// Answers 403 when an unverified user attempts a restricted action, otherwise hands `res` back.
+pub fn require_verified(res: Response, logged_id: i32, action: Restricted) -> Option<Response> {
+    let allowed = match action {
+        Restricted::Articles => *ALLOW_UNVERIFIED_ARTICLES,
+        Restricted::Comments => *ALLOW_UNVERIFIED_COMMENTS,
+    };
+
+    #[cfg(feature = "diesel")]
+    {
+        if !allowed && !is_verified(logged_id) {
+            let errors = ValidationErrors::with("email", "must be verified first");
+            send_errors(res, StatusCode::Forbidden, errors);
+            return None;
+        }
+    }
+    Some(res)
+}
+
+/// Mails a link that verifies `user.email`, and only that address. Links sent before stop
+/// working, they may be for an address the user has left since.
+#[cfg(feature = "diesel")]
+pub fn send_verification(user: &User) {
+    use schema::emailverifications;
+
+    let verification_token = new_random_token();
+    let now = Utc::now().naive_utc();
+    let new_verification = NewEmailVerification {
+        userid: user.id,
+        tokenhash: &hash_token(&verification_token),
+        email: &user.email,
+        createdat: now,
+        expiresat: now + chrono::Duration::hours(*VERIFICATION_TOKEN_HOURS),
+    };
+
+    let connection = establish_connection();
+    diesel::delete(
+        emailverifications::table
+            .filter(emailverifications::userid.eq(user.id))
+            .filter(emailverifications::usedat.is_null()),
+    ).execute(&connection)
+        .expect("Error removing earlier email verifications");
+    let _verification: EmailVerification = diesel::insert(&new_verification)
+        .into(emailverifications::table)
+        .get_result(&connection)
+        .expect("Error saving email verification");
+
+    send_mail(
+        &user.email,
+        "Confirm your Conduit email address",
+        &format!(
+            "Hi {},\n\nplease confirm your email address by opening this link within {} hours:\n{}/verify?token={}",
+            user.username,
+            *VERIFICATION_TOKEN_HOURS,
+            MAIL.link_base,
+            verification_token
+        ),
+    );
+}
+
+#[cfg(feature = "diesel")]
+fn verify_email(verification_token: String) -> Option<UserResult> {
+    use schema::emailverifications;
+    use schema::users;
+
+    let connection = establish_connection();
+    let now = Utc::now().naive_utc();
+
+    // used up in the same statement that checks it, so it can't be used twice
+    let verification: EmailVerification = match diesel::update(
+        emailverifications::table
+            .filter(emailverifications::tokenhash.eq(hash_token(&verification_token)))
+            .filter(emailverifications::usedat.is_null())
+            .filter(emailverifications::expiresat.gt(now)),
+    ).set(emailverifications::usedat.eq(Some(now)))
+        .get_result(&connection)
+    {
+        Ok(verification) => verification,
+        Err(_) => return None,
+    };
+
+    // the link verifies the address it was sent to, not whatever the account has by now
+    diesel::update(
+        users::table
+            .filter(users::id.eq(verification.userid))
+            .filter(users::email.eq(verification.email)),
+    ).set(users::verified.eq(true))
+        .get_result(&connection)
+        .ok()
+        .map(|user| UserResult { user: user })
+}
+
+pub fn verify_email_handler(req: Request, res: Response, c: Captures) {
+    let (_, _) = prepare_parameters(req);
+
+    let caps = c.unwrap();
+    let url_params = &caps[0].replace("/api/users/verify?", "");
+
+    let mut verification_token = "";
+    for param in url_params.split('&') {
+        let name_value: Vec<&str> = param.split('=').collect();
+
+        if name_value[0] == "token" && name_value.len() > 1 {
+            verification_token = name_value[1];
+        }
+    }
+
+    #[cfg(feature = "diesel")]
+    {
+        match verify_email(verification_token.to_string()) {
+            Some(result) => process(res, Some, result),
+            None => {
+                let errors = ValidationErrors::with("token", "is invalid");
+                send_errors(res, StatusCode::UnprocessableEntity, errors)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+use hyper::Client;
+
+#[cfg(test)]
+#[test]
+fn unverified_article_test() {
+    let client = Client::new();
+    let (_, email) = register_unverified_jacob();
+    let jwt = login_jacob(email.to_owned(), user::JACOB_PASSWORD.to_string());
+
+    let body = r#"{"article": {"title": "How to train your dragon","description": "Ever wonder how?","body": "You have to believe"}}"#;
+
+    let mut res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+
+    let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.errors["email"], vec!["must be verified first".to_string()]);
+
+    verify_jacob(&email);
+
+    let res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: jwt }))
+        .body(&body.replace("dragon", &format!("dragon {}", since_the_epoch())))
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn changed_email_test() {
+    let client = Client::new();
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email.to_owned(), user::JACOB_PASSWORD.to_string());
+
+    let new_email = format!("changed-{}", email);
+    let body = format!(
+        r#"{{"user": {{"email": "{}", "currentPassword": "{}"}}}}"#,
+        new_email,
+        user::JACOB_PASSWORD
+    );
+    let mut res = client
+        .put("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: jwt }))
+        .body(&body)
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result: UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.user.email, new_email);
+    assert_eq!(result.user.verified, false);
+
+    verify_jacob(&new_email);
+}
+
+#[cfg(test)]
+#[test]
+fn stale_verification_test() {
+    let client = Client::new();
+    let (_, email) = register_unverified_jacob();
+    let jwt = login_jacob(email.to_owned(), user::JACOB_PASSWORD.to_string());
+    let stale_token = read_token_from_mail(&email, "/verify?token=");
+
+    let new_email = format!("changed-{}", email);
+    let body = format!(
+        r#"{{"user": {{"email": "{}", "currentPassword": "{}"}}}}"#,
+        new_email,
+        user::JACOB_PASSWORD
+    );
+    let res = client
+        .put("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(&body)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    // the link mailed to the old address doesn't verify the new one
+    let url = format!("http://localhost:6767/api/users/verify?token={}", stale_token);
+    let res = client.get(&url).send().unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+    let mut res = client
+        .get("http://localhost:6767/api/user")
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let result: UserResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(result.user.verified, false);
+
+    verify_jacob(&new_email);
+}
@@ -0,0 +1,828 @@
+use std::io;
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::Mutex;
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::Duration;
+
+use crypto::hmac::Hmac;
+use crypto::mac::Mac;
+use crypto::sha2::Sha256;
+use hyper::Client;
+use hyper::Url;
+use hyper::header::Headers;
+use hyper::net::{HttpStream, HttpsStream, NetworkConnector, SslClient};
+use hyper::server::{Request, Response};
+use hyper_native_tls::{NativeTlsClient, TlsStream};
+use reroute::Captures;
+use rustc_serialize::hex::ToHex;
+
+use super::*;
+
+pub static WEBHOOK_EVENTS: &'static [&'static str] = &[
+    "article.created",
+    "article.updated",
+    "article.deleted",
+    "comment.added",
+    "user.followed",
+];
+
+lazy_static! {
+    static ref MAX_ATTEMPTS : i32 = get_config()
+        .webhooks
+        .and_then(|c| c.max_attempts)
+        .unwrap_or(5);
+    static ref BACKOFF_BASE_SECONDS : i64 = get_config()
+        .webhooks
+        .and_then(|c| c.backoff_base_seconds)
+        .unwrap_or(30);
+    static ref TIMEOUT_SECONDS : u64 = get_config()
+        .webhooks
+        .and_then(|c| c.timeout_seconds)
+        .unwrap_or(10);
+    static ref POLL_SECONDS : u64 = get_config()
+        .webhooks
+        .and_then(|c| c.poll_seconds)
+        .unwrap_or(5);
+    static ref ALLOWED_HOSTS : Vec<String> = get_config()
+        .webhooks
+        .and_then(|c| c.allowed_hosts)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|host| host.to_lowercase())
+        .collect();
+    /// Wakes the delivery thread up when new deliveries are queued.
+    static ref DELIVERY_WAKER : Mutex<Option<Sender<()>>> = Mutex::new(None);
+}
+
+/// A webhook as its owner sees it. The secret is only shown once, when the webhook is created.
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+#[allow(non_snake_case)]
+pub struct WebhookEntry {
+    pub id: i32,
+    pub url: String,
+    pub events: Vec<String>,
+    pub global: bool,
+    pub createdAt: NaiveDateTime,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+impl WebhookEntry {
+    fn from_webhook(webhook: Webhook, with_secret: bool) -> WebhookEntry {
+        WebhookEntry {
+            id: webhook.id,
+            url: webhook.url,
+            events: webhook.events.split(',').map(|event| event.to_string()).collect(),
+            global: webhook.isGlobal,
+            createdAt: webhook.createdAt,
+            secret: if with_secret { Some(webhook.secret) } else { None },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct WebhookResult {
+    pub webhook: WebhookEntry,
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct WebhooksResult {
+    pub webhooks: Vec<WebhookEntry>,
+}
+
+impl Container<WebhookEntry> for WebhooksResult {
+    fn create_new_with_items(webhooks: Vec<WebhookEntry>) -> WebhooksResult {
+        WebhooksResult { webhooks: webhooks }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[derive(Debug)]
+pub struct WebhookDeliveriesResult {
+    pub deliveries: Vec<WebhookDelivery>,
+}
+
+impl Container<WebhookDelivery> for WebhookDeliveriesResult {
+    fn create_new_with_items(deliveries: Vec<WebhookDelivery>) -> WebhookDeliveriesResult {
+        WebhookDeliveriesResult { deliveries: deliveries }
+    }
+}
+
+/// What receivers get in the request body.
+#[derive(Serialize)]
+#[allow(non_snake_case)]
+struct WebhookPayload<'a, T: 'a> {
+    event: &'a str,
+    createdAt: NaiveDateTime,
+    data: &'a T,
+}
+
+#[derive(Serialize)]
+struct FollowedPayload {
+    follower: String,
+    following: String,
+}
+
+/// Hex HMAC-SHA256 of the payload, sent as `X-Conduit-Signature: sha256=<hex>` so receivers
+/// can tell the request came from us.
+pub fn sign_payload(secret: &str, payload: &str) -> String {
+    let mut hmac = Hmac::new(Sha256::new(), secret.as_bytes());
+    hmac.input(payload.as_bytes());
+    hmac.result().code().to_hex()
+}
+
+/// Seconds to wait after the `attempts`-th failed attempt, doubling from `base_seconds`.
+pub fn backoff_seconds(attempts: i32, base_seconds: i64) -> i64 {
+    let doublings = if attempts < 1 {
+        0
+    } else if attempts > 20 {
+        19
+    } else {
+        attempts - 1
+    };
+    base_seconds * (1i64 << doublings)
+}
+
+/// Addresses of the server's own network: loopback, private, link-local (cloud metadata
+/// services among them), shared and unspecified ones.
+pub fn is_internal_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => {
+            let octets = address.octets();
+            address.is_loopback() || address.is_private() || address.is_link_local() ||
+                address.is_unspecified() || address.is_broadcast() || octets[0] == 0 ||
+                (octets[0] == 100 && octets[1] & 0xc0 == 64)
+        }
+        IpAddr::V6(address) => {
+            let first = address.segments()[0];
+            match address.to_ipv4() {
+                // IPv4 mapped and compatible addresses reach the IPv4 network
+                Some(mapped) if !address.is_loopback() && !address.is_unspecified() => is_internal_address(IpAddr::V4(mapped)),
+                _ => address.is_loopback() || address.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80,
+            }
+        }
+    }
+}
+
+/// The addresses `host` stands for, as long as none is internal or internal ones are allowed.
+fn resolve_target(host: &str, port: u16, allow_internal: bool) -> Result<Vec<SocketAddr>, String> {
+    let host = host.trim_left_matches('[').trim_right_matches(']');
+    let addresses: Vec<SocketAddr> = match (host, port).to_socket_addrs() {
+        Ok(addresses) => addresses.collect(),
+        Err(_) => return Err("can't be resolved".to_string()),
+    };
+    if addresses.is_empty() {
+        return Err("can't be resolved".to_string());
+    }
+    if !allow_internal && addresses.iter().any(|address| is_internal_address(address.ip())) {
+        return Err("must be a public address".to_string());
+    }
+    Ok(addresses)
+}
+
+/// Internal addresses are for the hosts allowed in the [webhooks] section and for admins' webhooks.
+#[cfg(feature = "diesel")]
+fn allows_internal(host: &str, owner_id: i32) -> bool {
+    ALLOWED_HOSTS.contains(&host.to_lowercase()) ||
+        is_permitted(get_role(owner_id), owner_id, &Permission::ManageWebhooks, None)
+}
+
+/// Checks a new webhook's receiver may be reached, before anything is sent to it.
+#[cfg(feature = "diesel")]
+fn check_webhook_target(url: &str, owner_id: i32) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|_| "must be an http or https address".to_string())?;
+    let host = url.host_str().unwrap_or("");
+    let port = url.port_or_known_default().unwrap_or(80);
+    resolve_target(host, port, allows_internal(host, owner_id)).map(|_| ())
+}
+
+/// Connects to receivers only once their addresses are checked, and only to the checked
+/// addresses, so a name can't resolve to a public address at creation and to an internal one later.
+/// https receivers get TLS on top of the checked connection, verified against their host name.
+struct DeliveryConnector {
+    timeout: Duration,
+    allow_internal: bool,
+    tls: NativeTlsClient,
+}
+
+impl NetworkConnector for DeliveryConnector {
+    type Stream = HttpsStream<TlsStream<HttpStream>>;
+
+    fn connect(&self, host: &str, port: u16, scheme: &str) -> hyper::Result<Self::Stream> {
+        if scheme != "http" && scheme != "https" {
+            return Err(hyper::Error::Io(io::Error::new(io::ErrorKind::InvalidInput, format!("can't reach {} receivers", scheme))));
+        }
+        let addresses = resolve_target(host, port, self.allow_internal)
+            .map_err(|error| hyper::Error::Io(io::Error::new(io::ErrorKind::PermissionDenied, format!("receiver {}", error))))?;
+
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "receiver has no address");
+        for address in addresses {
+            match TcpStream::connect_timeout(&address, self.timeout) {
+                Ok(stream) => {
+                    // the handshake mustn't hang either
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    let stream = HttpStream(stream);
+                    return if scheme == "https" {
+                        self.tls.wrap_client(stream, host).map(HttpsStream::Https)
+                    } else {
+                        Ok(HttpsStream::Http(stream))
+                    };
+                }
+                Err(error) => last_error = error,
+            }
+        }
+        Err(hyper::Error::Io(last_error))
+    }
+}
+
+fn wake_delivery_thread() {
+    if let Some(ref waker) = *DELIVERY_WAKER.lock().unwrap() {
+        let _ = waker.send(());
+    }
+}
+
+/// Queues `event` for the webhooks of `owner_ids` that asked for it, and for the global ones.
+/// Delivery happens on the webhook thread, requests never wait for receivers.
+#[cfg(feature = "diesel")]
+pub fn dispatch_webhook_event<T: serde::Serialize>(event: &'static str, owner_ids: Vec<i32>, data: &T) {
+    use diesel::expression::dsl::any;
+    use schema::{webhookdeliveries, webhooks};
+
+    let connection = establish_connection();
+    let subscribed: Vec<Webhook> = webhooks::table
+        .filter(webhooks::userid.eq(any(&owner_ids)).or(webhooks::isglobal.eq(true)))
+        .load::<Webhook>(&connection)
+        .expect("Error loading webhooks")
+        .into_iter()
+        .filter(|webhook| webhook.events.split(',').any(|name| name == event))
+        .collect();
+    if subscribed.is_empty() {
+        return;
+    }
+
+    let now = Utc::now().naive_utc();
+    let payload = serde_json::to_string(&WebhookPayload {
+        event: event,
+        createdAt: now,
+        data: data,
+    }).unwrap();
+
+    for webhook in subscribed {
+        let delivery = NewWebhookDelivery {
+            webhookid: webhook.id,
+            event: event,
+            payload: &payload,
+            createdat: now,
+            nextattemptat: Some(now),
+        };
+        diesel::insert(&delivery)
+            .into(webhookdeliveries::table)
+            .execute(&connection)
+            .expect("Error queueing webhook delivery");
+    }
+    wake_delivery_thread();
+}
+
+#[cfg(feature = "diesel")]
+pub fn dispatch_followed(follower_id: i32, followed: &User) {
+    let follower = match get_user_by_id(follower_id) {
+        Some(result) => result.user.username,
+        None => return,
+    };
+    let payload = FollowedPayload {
+        follower: follower,
+        following: followed.username.to_owned(),
+    };
+    dispatch_webhook_event("user.followed", vec![followed.id, follower_id], &payload);
+}
+
+/// Posts a delivery to its receiver, any 2xx answer counts as delivered.
+fn send_delivery(webhook: &Webhook, delivery: &WebhookDelivery, allow_internal: bool) -> Result<i32, (Option<i32>, String)> {
+    let timeout = Duration::from_secs(*TIMEOUT_SECONDS);
+    let tls = NativeTlsClient::new().map_err(|error| (None, error.to_string()))?;
+    let mut client = Client::with_connector(DeliveryConnector {
+        timeout: timeout,
+        allow_internal: allow_internal,
+        tls: tls,
+    });
+    client.set_read_timeout(Some(timeout));
+    client.set_write_timeout(Some(timeout));
+
+    let mut headers = Headers::new();
+    headers.set(ContentType(Mime(
+        TopLevel::Application,
+        SubLevel::Json,
+        vec![(Attr::Charset, Value::Utf8)],
+    )));
+    headers.set_raw("X-Conduit-Event", vec![delivery.event.as_bytes().to_vec()]);
+    headers.set_raw("X-Conduit-Delivery", vec![delivery.id.to_string().into_bytes()]);
+    let signature = format!("sha256={}", sign_payload(&webhook.secret, &delivery.payload));
+    headers.set_raw("X-Conduit-Signature", vec![signature.into_bytes()]);
+
+    match client.post(&webhook.url).headers(headers).body(delivery.payload.as_str()).send() {
+        Ok(ref res) if res.status.is_success() => Ok(res.status.to_u16() as i32),
+        Ok(res) => Err((Some(res.status.to_u16() as i32), format!("receiver answered {}", res.status))),
+        Err(error) => Err((None, error.to_string())),
+    }
+}
+
+#[cfg(feature = "diesel")]
+fn record_attempt(connection: &PgConnection, delivery: &WebhookDelivery, outcome: Result<i32, (Option<i32>, String)>) -> QueryResult<usize> {
+    use schema::webhookdeliveries::dsl::*;
+
+    let now = Utc::now().naive_utc();
+    let attempt_count = delivery.attempts + 1;
+
+    let (new_status, response_status, last_error, next_attempt_at, delivered_at) = match outcome {
+        Ok(code) => ("delivered", Some(code), None, None, Some(now)),
+        Err((code, error)) => if attempt_count >= *MAX_ATTEMPTS {
+            ("failed", code, Some(error), None, None)
+        } else {
+            let wait = backoff_seconds(attempt_count, *BACKOFF_BASE_SECONDS);
+            ("pending", code, Some(error), Some(now + chrono::Duration::seconds(wait)), None)
+        },
+    };
+
+    diesel::update(webhookdeliveries.filter(id.eq(delivery.id)))
+        .set((
+            status.eq(new_status),
+            attempts.eq(attempt_count),
+            responsestatus.eq(response_status),
+            lasterror.eq(last_error),
+            nextattemptat.eq(next_attempt_at),
+            deliveredat.eq(delivered_at),
+        ))
+        .execute(connection)
+}
+
+/// Sends whatever is due, first attempts and retries alike. Database errors end the round,
+/// what's left is still due the next time.
+#[cfg(feature = "diesel")]
+fn deliver_due(connection: &PgConnection) -> QueryResult<()> {
+    use schema::{webhookdeliveries, webhooks};
+
+    let due: Vec<WebhookDelivery> = webhookdeliveries::table
+        .filter(webhookdeliveries::status.eq("pending"))
+        .filter(webhookdeliveries::nextattemptat.le(Utc::now().naive_utc()))
+        .order(webhookdeliveries::id.asc())
+        .limit(100)
+        .load(connection)?;
+
+    for delivery in due {
+        let webhook: Webhook = match webhooks::table
+            .filter(webhooks::id.eq(delivery.webhookid))
+            .first(connection)
+            .optional()?
+        {
+            Some(webhook) => webhook,
+            None => continue,
+        };
+        // checked again on every attempt, the address behind a name can change
+        let host = Url::parse(&webhook.url).ok().and_then(|url| url.host_str().map(|host| host.to_string()));
+        let allow_internal = host.map_or(false, |host| allows_internal(&host, webhook.userid));
+        let outcome = send_delivery(&webhook, &delivery, allow_internal);
+        record_attempt(connection, &delivery, outcome)?;
+    }
+    Ok(())
+}
+
+/// Runs deliveries as they are queued, and retries every `poll_seconds`.
+#[cfg(feature = "diesel")]
+pub fn start_webhook_thread() {
+    let (waker, wakeups) = channel();
+    *DELIVERY_WAKER.lock().unwrap() = Some(waker);
+    let poll = ::std::time::Duration::from_secs(*POLL_SECONDS);
+
+    ::std::thread::spawn(move || loop {
+        if let Some(connection) = try_establish_connection() {
+            if let Err(error) = deliver_due(&connection) {
+                println!("Error delivering webhooks: {}", error);
+            }
+        }
+        if let Err(RecvTimeoutError::Disconnected) = wakeups.recv_timeout(poll) {
+            break;
+        }
+    });
+}
+
+#[cfg(feature = "diesel")]
+fn add_webhook(params: (i32, AddWebhookDetail)) -> Option<WebhookResult> {
+    use schema::webhooks;
+
+    let (user_id, detail) = params;
+    let connection = establish_connection();
+
+    let secret = new_random_token();
+    let mut events = detail.events;
+    events.sort();
+    events.dedup();
+    let events = events.join(",");
+    let new_webhook = NewWebhook {
+        userid: user_id,
+        url: &detail.url,
+        secret: &secret,
+        events: &events,
+        isglobal: detail.global,
+        createdat: Utc::now().naive_utc(),
+    };
+    let webhook: Webhook = diesel::insert(&new_webhook)
+        .into(webhooks::table)
+        .get_result(&connection)
+        .expect("Error saving webhook");
+
+    Some(WebhookResult { webhook: WebhookEntry::from_webhook(webhook, true) })
+}
+
+pub fn create_webhook_handler(req: Request, res: Response, _: Captures) {
+    let (body, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let container: AddWebhook = match serde_json::from_str(&body) {
+        Ok(container) => container,
+        Err(_) => return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("webhook", "can't be read")),
+    };
+    let errors = container.webhook.validate();
+    if !errors.is_empty() {
+        return send_errors(res, StatusCode::UnprocessableEntity, errors);
+    }
+
+    // global webhooks hear about everyone's content
+    let res = if container.webhook.global {
+        match require_permission(res, logged_id, Permission::ManageWebhooks, None) {
+            Some(res) => res,
+            None => return,
+        }
+    } else {
+        res
+    };
+
+    #[cfg(feature = "diesel")]
+    {
+        if let Err(error) = check_webhook_target(&container.webhook.url, logged_id) {
+            return send_errors(res, StatusCode::UnprocessableEntity, ValidationErrors::with("url", &error));
+        }
+
+        process(res, add_webhook, (logged_id, container.webhook));
+    }
+}
+
+fn webhooks_result(_: WebhooksResult) {}
+
+#[cfg(feature = "diesel")]
+fn get_webhooks(user_id: i32) -> Vec<WebhookEntry> {
+    use schema::webhooks::dsl::*;
+
+    let connection = establish_connection();
+    webhooks
+        .filter(userid.eq(user_id))
+        .order(id.asc())
+        .load::<Webhook>(&connection)
+        .expect("Error loading webhooks")
+        .into_iter()
+        .map(|webhook| WebhookEntry::from_webhook(webhook, false))
+        .collect()
+}
+
+pub fn list_webhooks_handler(req: Request, res: Response, _: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    #[cfg(feature = "diesel")]
+    process_container(res, webhooks_result, get_webhooks, logged_id);
+}
+
+/// Looks up the webhook in the path and checks the logged user may manage it, answering
+/// 404 or 403 otherwise.
+#[cfg(feature = "diesel")]
+fn require_webhook(res: Response, logged_id: i32, path: &str) -> Option<(Response, Webhook)> {
+    use schema::webhooks::dsl::*;
+
+    let webhook_id = path.replace("/api/webhooks/", "")
+        .split(|c| c == '/' || c == '?')
+        .next()
+        .and_then(|value| value.parse::<i32>().ok())
+        .unwrap_or(0);
+
+    let connection = establish_connection();
+    let webhook: Webhook = match webhooks.filter(id.eq(webhook_id)).first(&connection) {
+        Ok(webhook) => webhook,
+        Err(_) => {
+            send_errors(res, StatusCode::NotFound, ValidationErrors::with("webhook", "not found"));
+            return None;
+        }
+    };
+    let res = match require_permission(res, logged_id, Permission::ManageWebhooks, Some(webhook.userid)) {
+        Some(res) => res,
+        None => return None,
+    };
+    Some((res, webhook))
+}
+
+#[cfg(feature = "diesel")]
+fn delete_webhook(webhook_id: i32) -> Option<bool> {
+    use schema::{webhookdeliveries, webhooks};
+
+    let connection = establish_connection();
+    diesel::delete(webhookdeliveries::table.filter(webhookdeliveries::webhookid.eq(webhook_id)))
+        .execute(&connection)
+        .expect("Error deleting webhook deliveries");
+    diesel::delete(webhooks::table.filter(webhooks::id.eq(webhook_id)))
+        .execute(&connection)
+        .expect("Error deleting webhook");
+    None
+}
+
+pub fn delete_webhook_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, webhook) = match require_webhook(res, logged_id, &caps[0]) {
+            Some(found) => found,
+            None => return,
+        };
+
+        process(res, delete_webhook, webhook.id);
+    }
+}
+
+fn deliveries_result(_: WebhookDeliveriesResult) {}
+
+/// The delivery log of a webhook, newest first.
+#[cfg(feature = "diesel")]
+fn get_deliveries(params: (i32, i64, i64)) -> Vec<WebhookDelivery> {
+    use schema::webhookdeliveries::dsl::*;
+
+    let (webhook_id, offset, limit) = params;
+    let connection = establish_connection();
+    webhookdeliveries
+        .filter(webhookid.eq(webhook_id))
+        .order(id.desc())
+        .offset(offset)
+        .limit(limit)
+        .load(&connection)
+        .expect("Error loading webhook deliveries")
+}
+
+pub fn list_deliveries_handler(req: Request, res: Response, c: Captures) {
+    let (_, logged_id) = prepare_parameters(req);
+    let res = match require_login(res, logged_id) {
+        Some(res) => res,
+        None => return,
+    };
+
+    let caps = c.unwrap();
+    let (offset, limit) = parse_paging(caps[0].splitn(2, '?').nth(1).unwrap_or(""));
+
+    #[cfg(feature = "diesel")]
+    {
+        let (res, webhook) = match require_webhook(res, logged_id, &caps[0]) {
+            Some(found) => found,
+            None => return,
+        };
+
+        process_container(res, deliveries_result, get_deliveries, (webhook.id, offset, limit));
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn sign_payload_test() {
+    // RFC 4231, test case 2
+    assert_eq!(
+        sign_payload("Jefe", "what do ya want for nothing?"),
+        "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+    );
+
+    assert_eq!(backoff_seconds(1, 30), 30);
+    assert_eq!(backoff_seconds(2, 30), 60);
+    assert_eq!(backoff_seconds(4, 30), 240);
+}
+
+#[cfg(test)]
+#[test]
+fn internal_address_test() {
+    for address in &["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "0.0.0.0",
+                     "100.64.0.1", "::1", "::", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+        assert!(is_internal_address(address.parse().unwrap()), "{} is internal", address);
+    }
+    for address in &["93.184.216.34", "172.32.0.1", "2606:2800:220:1::1", "::ffff:93.184.216.34"] {
+        assert!(!is_internal_address(address.parse().unwrap()), "{} is public", address);
+    }
+
+    assert!(resolve_target("169.254.169.254", 80, false).is_err());
+    assert!(resolve_target("[::1]", 80, false).is_err());
+    assert_eq!(resolve_target("127.0.0.1", 9, true).unwrap().len(), 1);
+}
+
+#[cfg(test)]
+#[test]
+fn https_connector_test() {
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    // a receiver that only looks at what it's sent first
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let receiver = ::std::thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut first = [0u8; 1];
+        stream.read_exact(&mut first).unwrap();
+        first[0]
+    });
+
+    let connector = DeliveryConnector {
+        timeout: Duration::from_secs(2),
+        allow_internal: true,
+        tls: NativeTlsClient::new().unwrap(),
+    };
+    // the stand-in can't finish the handshake, it's enough that one was started
+    assert!(connector.connect("127.0.0.1", port, "https").is_err());
+    assert_eq!(receiver.join().unwrap(), 0x16, "a TLS handshake record");
+
+    assert!(connector.connect("127.0.0.1", port, "ftp").is_err());
+}
+
+#[cfg(test)]
+fn create_webhook_for(jwt: &str, url: &str) -> WebhookEntry {
+    let client = Client::new();
+    let body = format!(r#"{{"webhook": {{"url": "{}", "events": ["article.created"]}}}}"#, url);
+    let mut res = client
+        .post("http://localhost:6767/api/webhooks")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(body.as_str())
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    assert_eq!(res.status, hyper::Ok);
+
+    let result: WebhookResult = serde_json::from_str(&buffer).unwrap();
+    result.webhook
+}
+
+/// Polls the delivery log until `done` holds for the latest delivery, for up to 20 seconds.
+#[cfg(test)]
+fn wait_for_delivery(jwt: &str, webhook_id: i32, done: fn(&WebhookDelivery) -> bool) -> WebhookDelivery {
+    let client = Client::new();
+    for _ in 0..100 {
+        let mut res = client
+            .get(&format!("http://localhost:6767/api/webhooks/{}/deliveries", webhook_id))
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        let mut result: WebhookDeliveriesResult = serde_json::from_str(&buffer).unwrap();
+        if !result.deliveries.is_empty() && done(&result.deliveries[0]) {
+            return result.deliveries.remove(0);
+        }
+        ::std::thread::sleep(::std::time::Duration::from_millis(200));
+    }
+    panic!("webhook delivery didn't happen in time");
+}
+
+#[cfg(test)]
+fn create_article_as(jwt: &str, webhook_id: i32) {
+    let client = Client::new();
+    let title = format!("How to train your dragon {}-{}", since_the_epoch(), webhook_id);
+    let body = format!(r#"{{"article": {{"title": "{}", "description": "Ever wonder how?", "body": "You have to believe"}}}}"#, title);
+    let res = client
+        .post("http://localhost:6767/api/articles")
+        .header(Authorization(Bearer { token: jwt.to_owned() }))
+        .body(body.as_str())
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::Ok);
+}
+
+#[cfg(test)]
+#[test]
+fn webhook_delivery_test() {
+    use std::sync::Arc;
+
+    // stand-in receiver, remembers what it was sent
+    let received: Arc<Mutex<Vec<(String, String, String)>>> = Arc::new(Mutex::new(Vec::new()));
+    let log = received.clone();
+    let listening = hyper::Server::http("127.0.0.1:0")
+        .unwrap()
+        .handle(move |mut req: Request, res: Response| {
+            let mut body = String::new();
+            let _ = req.read_to_string(&mut body);
+            let header = |name: &str| {
+                req.headers
+                    .get_raw(name)
+                    .map(|values| String::from_utf8_lossy(&values[0]).into_owned())
+                    .unwrap_or_default()
+            };
+            let entry = (header("X-Conduit-Event"), header("X-Conduit-Signature"), body);
+            log.lock().unwrap().push(entry);
+            let _ = res.send(b"ok");
+        })
+        .unwrap();
+    let receiver_url = format!("http://{}/hooks", listening.socket);
+    // the receiver runs until the tests are over, dropping it would wait for that
+    ::std::mem::forget(listening);
+
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    let webhook = create_webhook_for(&jwt, &receiver_url);
+    assert_eq!(webhook.events, vec!["article.created".to_string()]);
+    let secret = webhook.secret.unwrap();
+
+    create_article_as(&jwt, webhook.id);
+
+    let delivery = wait_for_delivery(&jwt, webhook.id, |delivery| delivery.status == "delivered");
+    assert_eq!(delivery.event, "article.created");
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(delivery.responseStatus, Some(200));
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.len(), 1);
+    let (ref event, ref signature, ref body) = received[0];
+    assert_eq!(event, "article.created");
+    assert_eq!(signature, &format!("sha256={}", sign_payload(&secret, body)));
+    assert!(body.starts_with(r#"{"event":"article.created""#));
+
+    // the secret isn't shown again
+    let mut res = Client::new()
+        .get("http://localhost:6767/api/webhooks")
+        .header(Authorization(Bearer { token: jwt }))
+        .send()
+        .unwrap();
+    let mut buffer = String::new();
+    res.read_to_string(&mut buffer).unwrap();
+    let listed: WebhooksResult = serde_json::from_str(&buffer).unwrap();
+    assert_eq!(listed.webhooks.len(), 1);
+    assert_eq!(listed.webhooks[0].secret, None);
+}
+
+#[cfg(test)]
+#[test]
+fn webhook_retry_test() {
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+    // nothing listens on the discard port
+    let webhook = create_webhook_for(&jwt, "http://127.0.0.1:9");
+
+    create_article_as(&jwt, webhook.id);
+
+    let delivery = wait_for_delivery(&jwt, webhook.id, |delivery| delivery.attempts > 0);
+    assert_eq!(delivery.status, "pending");
+    assert!(delivery.lastError.is_some());
+    assert!(delivery.nextAttemptAt.unwrap() > delivery.createdAt);
+}
+
+#[cfg(test)]
+#[test]
+fn internal_webhook_test() {
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+
+    for url in &["http://169.254.169.254/latest/meta-data", "http://10.0.0.1:8080/hooks", "http://[::1]:6767/"] {
+        let mut res = Client::new()
+            .post("http://localhost:6767/api/webhooks")
+            .header(Authorization(Bearer { token: jwt.to_owned() }))
+            .body(format!(r#"{{"webhook": {{"url": "{}", "events": ["article.created"]}}}}"#, url).as_str())
+            .send()
+            .unwrap();
+        let mut buffer = String::new();
+        res.read_to_string(&mut buffer).unwrap();
+        assert_eq!(res.status, hyper::status::StatusCode::UnprocessableEntity);
+
+        let result: ValidationErrors = serde_json::from_str(&buffer).unwrap();
+        assert_eq!(result.errors["url"], vec!["must be a public address".to_string()]);
+    }
+}
+
+#[cfg(test)]
+#[test]
+fn global_webhook_test() {
+    let (_, email) = register_jacob();
+    let jwt = login_jacob(email, user::JACOB_PASSWORD.to_string());
+
+    let res = Client::new()
+        .post("http://localhost:6767/api/webhooks")
+        .header(Authorization(Bearer { token: jwt }))
+        .body(r#"{"webhook": {"url": "http://127.0.0.1:9", "events": ["comment.added"], "global": true}}"#)
+        .send()
+        .unwrap();
+    assert_eq!(res.status, hyper::status::StatusCode::Forbidden);
+}